@@ -1,21 +1,31 @@
 use cosmwasm_std::{
-    entry_point, to_binary, Coin, Deps, DepsMut, Env, MessageInfo, Response,from_binary,Binary,
-    StdResult, Uint128,CosmosMsg,WasmMsg,Decimal,BankMsg,Order
+    entry_point, to_binary, Deps, DepsMut, Env, MessageInfo, Response,from_binary,Binary,
+    StdResult, Uint128,CosmosMsg,WasmMsg,Decimal,Order,Storage,StdError,Addr,Event
 };
 
-use cw2::set_contract_version;
-use cw20::{ Cw20ExecuteMsg,Cw20ReceiveMsg};
-use cw721::{Cw721ReceiveMsg, Cw721ExecuteMsg};
+use cw2::{get_contract_version, set_contract_version};
+use cw_storage_plus::{Bound, PrimaryKey};
+use cw20::Cw20ReceiveMsg;
+use cw721::{Cw721ReceiveMsg, Cw721ExecuteMsg, Cw721QueryMsg, OwnerOfResponse, TokensResponse};
 
 use crate::error::{ContractError};
-use crate::msg::{ ExecuteMsg, InstantiateMsg, QueryMsg,SellNft, BuyNft};
-use crate::state::{State,CONFIG,Offering, OFFERINGS,Asset,UserInfo, MEMBERS,SALEHISTORY,PRICEINFO,SaleInfo,PriceInfo, COLLECTIONINFO, CollectionInfo, TOKENADDRESS, TVL, TvlInfo};
-use crate::package::{QueryOfferingsResult};
+use crate::msg::{ ExecuteMsg, InstantiateMsg, MigrateMsg, QueryMsg,SellNft, BuyNft, NftHookMsg, StartAuction, TokenHookMsg, Cw2981QueryMsg, RoyaltyQueryMsg};
+use crate::state::{State,CONFIG,PENDING_OWNER,PendingOwner,ROLES,Role,PAUSED,PAUSED_COLLECTIONS,nft_transfers,NFT_TRANSFER_COUNT,NftTransfer,TransferConsent,Offering, offerings,UserInfo, MEMBERS,sale_history,SaleInfo,PriceInfo, VOLUMES, VolumeInfo, GLOBAL_STATS, GlobalStats, GLOBAL_VOLUMES, BUYERS, SELLERS, COLLECTIONINFO, CollectionInfo, TOKENADDRESS, TVL, TvlInfo, auctions, Auction, Bid, DutchAuction, TokenOffer, token_offers, CollectionOffer, COLLECTION_OFFERS, DEPOSITS, FeeTier, RoyaltyMode, price_key, FIXED_PRICE, DUTCH_AUCTION};
+use crate::package::{QueryOfferingsResult, QueryAuctionsResult, FeeInfo, RoyaltiesInfoResponse, GlobalStatsResponse,
+    OfferingsResponse, AllOfferingsResponse, CollectionOffering, FloorPriceResponse, SaleResult, SalesResponse, PauseStatusResponse,
+    OrphanedNft, EscrowReconciliationResponse, DanglingOfferingsResponse};
+use crate::asset::{Asset, AssetInfo};
+use crate::migrations::migrate_storage;
 
 
 const CONTRACT_NAME: &str = "Hope_Market_Place";
 const CONTRACT_VERSION: &str = env!("CARGO_PKG_VERSION");
 
+const DEFAULT_LIMIT: u32 = 10;
+const MAX_LIMIT: u32 = 30;
+/// Seconds between proposing and executing a transfer of escrowed nfts
+const NFT_TRANSFER_DELAY: u64 = 3 * 24 * 60 * 60;
+
 #[entry_point]
 pub fn instantiate(
     deps: DepsMut,
@@ -24,12 +34,23 @@ pub fn instantiate(
     msg: InstantiateMsg,
 ) -> Result<Response, ContractError> {
     set_contract_version(deps.storage, CONTRACT_NAME, CONTRACT_VERSION)?;
+    let fee = msg.fee.unwrap_or_else(Decimal::zero);
+    validate_fee(fee)?;
+    let fee_collector = match msg.fee_collector {
+        Some(fee_collector) => deps.api.addr_validate(&fee_collector)?.to_string(),
+        None => msg.owner.clone(),
+    };
     let state = State {
         owner:msg.owner,
         new:true,
+        fee,
+        fee_collector,
+        fee_tiers: vec![],
     };
     CONFIG.save(deps.storage,&state)?;
-    Ok(Response::default())
+    Ok(Response::new()
+        .add_attribute("action", "instantiate")
+        .add_attribute("owner", state.owner))
 }
 
 #[entry_point]
@@ -39,27 +60,60 @@ pub fn execute(
     info: MessageInfo,
     msg: ExecuteMsg,
 ) -> Result<Response, ContractError> {
-    match msg {
+    let response = match msg {
     ExecuteMsg::ReceiveNft(msg) =>execute_receive_nft(deps,env,info,msg),
     ExecuteMsg::Receive(msg) =>execute_receive(deps,env,info,msg),
-    ExecuteMsg::BuyNft { offering_id,nft_address } =>execute_buy_nft(deps,env,info,offering_id,nft_address),
+    ExecuteMsg::BuyNft { offering_id,nft_address,token_id } =>execute_buy_nft(deps,env,info,offering_id,nft_address,token_id),
     ExecuteMsg::WithdrawNft { offering_id,nft_address } => execute_withdraw(deps,env,info,offering_id,nft_address),
     ExecuteMsg::AddTokenAddress { symbol, address }  => execute_token_address(deps,env,info,symbol,address),
-    ExecuteMsg::ChangeOwner { address } =>execute_change_owner(deps,env,info,address),
-    ExecuteMsg::AddCollection { royalty_portion, members,nft_address ,offering_id,sale_id} =>execute_add_collection(deps,env,info,royalty_portion,members,nft_address,offering_id,sale_id),
-    ExecuteMsg::UpdateCollection { royalty_portion, members,nft_address } =>execute_update_collection(deps,env,info,royalty_portion,members,nft_address),
-    ExecuteMsg:: FixNft{address,token_id} =>execute_fix_nft(deps,env,info,address,token_id),
+    ExecuteMsg::ProposeOwner { address, expires } =>execute_propose_owner(deps,env,info,address,expires),
+    ExecuteMsg::AcceptOwnership {} =>execute_accept_ownership(deps,env,info),
+    ExecuteMsg::CancelProposal {} =>execute_cancel_proposal(deps,env,info),
+    ExecuteMsg::RenounceOwnership {} =>execute_renounce_ownership(deps,env,info),
+    ExecuteMsg::Pause { nft_address } =>execute_set_paused(deps,env,info,nft_address,true),
+    ExecuteMsg::Unpause { nft_address } =>execute_set_paused(deps,env,info,nft_address,false),
+    ExecuteMsg::GrantRole { role, address } =>execute_grant_role(deps,env,info,role,address),
+    ExecuteMsg::RevokeRole { role, address } =>execute_revoke_role(deps,env,info,role,address),
+    ExecuteMsg::AddCollection { royalty_portion, members,nft_address ,offering_id,sale_id,fee,royalty_mode} =>execute_add_collection(deps,env,info,royalty_portion,members,nft_address,offering_id,sale_id,fee,royalty_mode),
+    ExecuteMsg::UpdateCollection { royalty_portion, members,nft_address,fee,clear_fee,royalty_mode } =>execute_update_collection(deps,env,info,royalty_portion,members,nft_address,fee,clear_fee.unwrap_or(false),royalty_mode),
     ExecuteMsg::SetOfferings { address, offering }=>execute_set_offerings(deps,env,info,address,offering),
     ExecuteMsg::SetTvl { address, tvl } =>execute_set_tvl(deps,env,info,address,tvl),
-    ExecuteMsg::Migrate { address, dest, token_id }=>execute_migrate(deps,env,info,address,dest,token_id),
-    ExecuteMsg::SetSaleHistory { address, history }=>execute_history(deps,env,info,address,history)
+    ExecuteMsg::ProposeNftTransfer { nft_address, token_ids, recipient } =>execute_propose_nft_transfer(deps,env,info,nft_address,token_ids,recipient),
+    ExecuteMsg::ConsentNftTransfer { transfer_id } =>execute_consent_nft_transfer(deps,env,info,transfer_id),
+    ExecuteMsg::ExecuteNftTransfer { transfer_id } =>execute_nft_transfer(deps,env,info,transfer_id),
+    ExecuteMsg::CancelNftTransfer { transfer_id } =>execute_cancel_nft_transfer(deps,env,info,transfer_id),
+    ExecuteMsg::ClaimOrphanedNft { nft_address, token_id } =>execute_claim_orphaned_nft(deps,env,info,nft_address,token_id),
+    ExecuteMsg::SetSaleHistory { address, history }=>execute_history(deps,env,info,address,history),
+    ExecuteMsg::PlaceBid { nft_address, auction_id } =>execute_place_bid(deps,env,info,nft_address,auction_id),
+    ExecuteMsg::SettleAuction { nft_address, auction_id } =>execute_settle_auction(deps,env,info,nft_address,auction_id),
+    ExecuteMsg::MakeOffer { nft_address, token_id, price, expires } =>execute_make_offer(deps,env,info,nft_address,token_id,price,expires),
+    ExecuteMsg::CancelOffer { nft_address, token_id } =>execute_cancel_offer(deps,env,info,nft_address,token_id),
+    ExecuteMsg::MakeCollectionOffer { nft_address, price, quantity, expires } =>execute_make_collection_offer(deps,env,info,nft_address,price,quantity,expires),
+    ExecuteMsg::CancelCollectionOffer { nft_address } =>execute_cancel_collection_offer(deps,env,info,nft_address),
+    ExecuteMsg::ReclaimExpired { nft_address, offering_id } =>execute_reclaim_expired(deps,env,info,nft_address,offering_id),
+    ExecuteMsg::UpdateOffering { nft_address, offering_id, list_price } =>execute_update_offering(deps,env,info,nft_address,offering_id,list_price),
+    ExecuteMsg::UpdateConfig { fee, fee_collector, fee_tiers } =>execute_update_config(deps,env,info,fee,fee_collector,fee_tiers)
+    }?;
+    Ok(with_event(response))
 }
+
+/// Repeats the attributes of a response in a `wasm-<action>` event for indexers
+fn with_event(response: Response) -> Response {
+    let action = response.attributes.iter().find(|attr| attr.key == "action").map(|attr| attr.value.clone());
+    match action {
+        Some(action) => {
+            let attributes = response.attributes.iter().filter(|attr| attr.key != "action").cloned();
+            let event = Event::new(action).add_attributes(attributes);
+            response.add_event(event)
+        }
+        None => response
+    }
 }
 
 
 fn execute_receive_nft(
     deps: DepsMut,
-    _env:Env,
+    env:Env,
     info: MessageInfo,
     rcv_msg: Cw721ReceiveMsg,
 )-> Result<Response, ContractError> {
@@ -71,25 +125,80 @@ fn execute_receive_nft(
     }
 
     let mut collection_info = collection_info.unwrap();
-    
 
-    let msg:SellNft = from_binary(&rcv_msg.msg)?;
+    let seller = deps.api.addr_validate(&rcv_msg.sender)?.to_string();
     let nft_address = info.sender.to_string();
+    assert_not_paused(deps.storage, &nft_address)?;
+    let mut action = "sell_nft";
+    let off = match from_binary(&rcv_msg.msg) {
+        Ok(NftHookMsg::StartAuction(msg)) => {
+            return execute_start_auction(deps, env, info, rcv_msg, msg);
+        }
+        Ok(NftHookMsg::AcceptOffer { bidder }) => {
+            return execute_accept_offer(deps, env, info, rcv_msg, bidder);
+        }
+        Ok(NftHookMsg::AcceptCollectionOffer { bidder }) => {
+            return execute_accept_collection_offer(deps, env, info, rcv_msg, bidder);
+        }
+        Ok(NftHookMsg::StartDutchAuction(msg)) => {
+            action = "start_dutch_auction";
+            if msg.end_time <= msg.start_time || msg.end_time <= env.block.time.seconds() {
+                return Err(ContractError::InvalidEndTime {});
+            }
+            if msg.floor_price > msg.start_price.amount {
+                return Err(ContractError::InvalidFloorPrice {});
+            }
+            validate_price(deps.as_ref(), &msg.start_price)?;
+            Offering {
+                nft_address: nft_address.clone(),
+                token_id: rcv_msg.token_id,
+                seller,
+                list_price: msg.start_price,
+                dutch_auction: Some(DutchAuction {
+                    floor_price: msg.floor_price,
+                    start_time: msg.start_time,
+                    end_time: msg.end_time,
+                    step: msg.step
+                }),
+                expires_at: None
+            }
+        }
+        Err(_) => {
+            let msg:SellNft = from_binary(&rcv_msg.msg)?;
+            if matches!(msg.expires_at, Some(expires_at) if expires_at <= env.block.time.seconds()) {
+                return Err(ContractError::InvalidExpiration {});
+            }
+            validate_price(deps.as_ref(), &msg.list_price)?;
+            Offering {
+                nft_address: nft_address.clone(),
+                token_id: rcv_msg.token_id,
+                seller,
+                list_price: msg.list_price,
+                dutch_auction: None,
+                expires_at: msg.expires_at
+            }
+        }
+    };
     
-    collection_info.offering_id = collection_info.offering_id + 1;
+    collection_info.offering_id += 1;
+    collection_info.num_offerings += 1;
    
     COLLECTIONINFO.save(deps.storage, &nft_address,&collection_info)?;
-
-    let off = Offering {
-        token_id: rcv_msg.token_id.clone(),
-        seller: deps.api.addr_validate(&rcv_msg.sender)?.to_string(),
-        list_price: msg.list_price.clone(),
-    };
-
-    
-  
-    OFFERINGS.save(deps.storage, (&nft_address,&collection_info.offering_id.to_string()), &off)?;
-    Ok(Response::default())
+    update_global_stats(deps.storage, |stats| stats.active_listings += 1)?;
+
+    offerings().save(deps.storage, (&nft_address,collection_info.offering_id), &off)?;
+    DEPOSITS.save(deps.storage, (&nft_address, &off.token_id), &off.seller)?;
+    let mut response = Response::new()
+        .add_attribute("action", action)
+        .add_attribute("collection", nft_address)
+        .add_attribute("token_id", off.token_id.clone())
+        .add_attribute("offering_id", collection_info.offering_id.to_string())
+        .add_attribute("seller", off.seller.clone())
+        .add_attribute("price", off.list_price.to_string());
+    if let Some(auction) = &off.dutch_auction {
+        response = response.add_attribute("floor_price", auction.floor_price);
+    }
+    Ok(response)
 }
 
 fn execute_receive(
@@ -100,144 +209,97 @@ fn execute_receive(
 )-> Result<Response, ContractError> {
     let _state = CONFIG.load(deps.storage)?;
 
-    let token_symbol = TOKENADDRESS.may_load(deps.storage, &info.sender.to_string())?;
-
-    if token_symbol == None{
+    if !TOKENADDRESS.has(deps.storage, info.sender.as_str()) {
         return Err(ContractError::WrongTokenContractError {  })
     }
-    let token_symbol = token_symbol.unwrap();
-
-    let msg:BuyNft = from_binary(&rcv_msg.msg)?;
-    deps.api.addr_validate(&msg.nft_address)?;
+    let asset_info = AssetInfo::Token { contract_addr: info.sender.to_string() };
 
-    let collection_info = COLLECTIONINFO.may_load(deps.storage, &msg.nft_address)?;
-    if collection_info == None{
-        return Err(ContractError::WrongNFTContractError {  })
+    if let Ok(TokenHookMsg::PlaceBid { nft_address, auction_id }) = from_binary(&rcv_msg.msg) {
+        let auction = auctions().load(deps.storage, (&nft_address, &auction_id))?;
+        if !auction.reserve_price.info.equal(&asset_info) {
+            return Err(ContractError::NotEnoughFunds {});
+        }
+        let bid = Bid {
+            bidder: deps.api.addr_validate(&rcv_msg.sender)?.to_string(),
+            amount: rcv_msg.amount,
+        };
+        return place_bid(deps, env, nft_address, auction_id, bid);
     }
 
-    let off = OFFERINGS.load(deps.storage, (&msg.nft_address,&msg.offering_id))?;
-
-    
-    if off.list_price.denom != token_symbol{
-        return Err(ContractError::NotEnoughFunds  { })
+    if let Ok(TokenHookMsg::MakeOffer { nft_address, token_id, expires }) = from_binary(&rcv_msg.msg) {
+        let offer = TokenOffer {
+            nft_address,
+            token_id,
+            bidder: deps.api.addr_validate(&rcv_msg.sender)?.to_string(),
+            price: Asset { info: asset_info, amount: rcv_msg.amount },
+            expires,
+        };
+        return make_offer(deps, env, offer);
     }
 
-    if off.list_price.amount != rcv_msg.amount{
-        return Err(ContractError::NotEnoughFunds  { })
+    if let Ok(TokenHookMsg::MakeCollectionOffer { nft_address, quantity, expires }) = from_binary(&rcv_msg.msg) {
+        let quantity = quantity.unwrap_or(1);
+        if quantity == 0 {
+            return Err(ContractError::InvalidQuantity {});
+        }
+        if !(rcv_msg.amount % Uint128::from(quantity)).is_zero() {
+            return Err(ContractError::NotEnoughFunds {});
+        }
+        let offer = CollectionOffer {
+            nft_address,
+            bidder: deps.api.addr_validate(&rcv_msg.sender)?.to_string(),
+            price: Asset { info: asset_info, amount: rcv_msg.amount / Uint128::from(quantity) },
+            quantity,
+            expires,
+        };
+        return make_collection_offer(deps, env, offer);
     }
 
-    let tvl = TVL.may_load(deps.storage, (&msg.nft_address,&off.list_price.denom))?;
-    let crr_tvl:Uint128;
-    if tvl == None {
-        crr_tvl = off.list_price.amount;
-    }
-    else {
-        crr_tvl = tvl.unwrap()+off.list_price.amount;
-    }
+    let msg:BuyNft = from_binary(&rcv_msg.msg)?;
+    deps.api.addr_validate(&msg.nft_address)?;
+    assert_not_paused(deps.storage, &msg.nft_address)?;
 
-    TVL.save(deps.storage,( &msg.nft_address,&off.list_price.denom), &crr_tvl)?;
-  
-    let members = MEMBERS.load(deps.storage,&msg.nft_address)?;
     let collection_info = COLLECTIONINFO.may_load(deps.storage, &msg.nft_address)?;
-
     if collection_info == None{
         return Err(ContractError::WrongNFTContractError {  })
     }
 
-    let collection_info = collection_info.unwrap();
+    let offering_id = offering_key(&msg.offering_id)?;
+    let off = offerings().load(deps.storage, (&msg.nft_address,offering_id))?;
 
-    if collection_info.offering_id == 1{    
-          OFFERINGS.remove( deps.storage, (&msg.nft_address,&msg.offering_id));
-          COLLECTIONINFO.update(deps.storage, &msg.nft_address,
-             |collection_info|->StdResult<_>{
-                    let mut collection_info = collection_info.unwrap();
-                    collection_info.offering_id = 0;
-                    Ok(collection_info)
-             })?;
+    
+    if !off.list_price.info.equal(&asset_info){
+        return Err(ContractError::NotEnoughFunds  { })
     }
 
-    else{
-        let crr_offering_id = collection_info.offering_id;
-        let offering = OFFERINGS.may_load(deps.storage, (&msg.nft_address,&crr_offering_id.to_string()))?;
-        
-        if offering!=None{
-            OFFERINGS.save(deps.storage, (&msg.nft_address,&msg.offering_id.to_string()), &offering.unwrap())?;
-           
-            COLLECTIONINFO.update(deps.storage, &msg.nft_address,
-                |collection_info|->StdResult<_>{
-                        let mut collection_info = collection_info.unwrap();
-                        collection_info.offering_id =collection_info.offering_id-1;
-                        Ok(collection_info)
-                })?;
-          OFFERINGS.remove( deps.storage, (&msg.nft_address,&crr_offering_id.to_string()));
-         }
-
-         
+    if matches!(&msg.token_id, Some(token_id) if *token_id != off.token_id) {
+        return Err(ContractError::TokenIdMismatch {});
     }
 
-    let mut messages:Vec<CosmosMsg> = vec![];
-    for user in members{
-        messages.push(CosmosMsg::Wasm(WasmMsg::Execute {
-                contract_addr: info.sender.to_string(),
-                funds: vec![],
-                msg: to_binary(&Cw20ExecuteMsg::Transfer { 
-                    recipient: user.address.clone(), 
-                    amount: rcv_msg.amount*collection_info.royalty_portion*user.portion })?,
-        }))
+    if off.is_expired(env.block.time.seconds()) {
+        return Err(ContractError::OfferingExpired {});
     }
 
-    let price_info = PRICEINFO.may_load(deps.storage,&msg.nft_address)?;
-    if price_info == None{
-        PRICEINFO.save(deps.storage,&msg.nft_address,&PriceInfo {
-            total_juno:Uint128::new(0) ,
-            total_hope: rcv_msg.amount })?;
-       }
-    else{
-        PRICEINFO.update(deps.storage,&msg.nft_address,
-        |price_info|->StdResult<_>{
-            let mut price_info = price_info.unwrap();
-            price_info.total_hope = price_info.total_hope + rcv_msg.amount;
-            Ok(price_info)
-        })?;}
-    let sale_id = collection_info.sale_id+1;
-
-    SALEHISTORY.save(deps.storage, (&msg.nft_address,&sale_id.to_string()),&SaleInfo { 
-        from:off.seller.clone(),
-        to: rcv_msg.sender.to_string(), 
-        denom: off.list_price.denom,
-        amount: rcv_msg.amount, 
-        time: env.block.time.seconds(),
-        nft_address:msg.nft_address.clone(),
-        token_id:off.token_id.clone()
-    })?;
+    let price = off.current_price(env.block.time.seconds());
+    check_payment(&off, price, rcv_msg.amount)?;
 
-    COLLECTIONINFO.update(deps.storage, &msg.nft_address, 
-        |collection_info|->StdResult<_>{
-            let mut collection_info = collection_info.unwrap();
-            collection_info.sale_id = sale_id;
-            Ok(collection_info)
-        }
-    )?;
-    
+    remove_offering(deps.storage, &msg.nft_address, offering_id)?;
 
-    Ok(Response::new()
-        .add_message(CosmosMsg::Wasm(WasmMsg::Execute {
-                contract_addr: msg.nft_address.to_string(),
-                funds: vec![],
-                msg: to_binary(&Cw721ExecuteMsg::TransferNft {
-                    recipient: deps.api.addr_validate(&rcv_msg.sender)?.to_string(),
-                    token_id: off.token_id.clone(),
-            })?,
-        }))
-        .add_message(CosmosMsg::Wasm(WasmMsg::Execute {
-                contract_addr: info.sender.to_string(),
-                funds: vec![],
-                msg: to_binary(&Cw20ExecuteMsg::Transfer { 
-                    recipient: off.seller, 
-                    amount: rcv_msg.amount*(Decimal::one()-collection_info.royalty_portion) })?,
-        }))
-        .add_messages(messages)
-)
+    let buyer = deps.api.addr_validate(&rcv_msg.sender)?;
+    let mut response = settle_sale(deps, "buy_nft", SaleInfo {
+        from: off.seller,
+        to: buyer.to_string(),
+        denom: asset_info.to_string(),
+        amount: price,
+        time: env.block.time.seconds(),
+        nft_address: msg.nft_address.clone(),
+        token_id: off.token_id
+    }, &asset_info)?;
+    if rcv_msg.amount > price {
+        response = response.add_message(Asset { info: asset_info, amount: rcv_msg.amount - price }.into_msg(buyer)?);
+    }
+
+    Ok(response.add_attribute("offering_id", msg.offering_id))
 }
 
 fn execute_buy_nft(
@@ -245,943 +307,4208 @@ fn execute_buy_nft(
     env:Env,
     info: MessageInfo,
     offering_id: String,
-    nft_address:String
+    nft_address:String,
+    token_id:Option<String>
 ) -> Result<Response, ContractError> {
+    assert_not_paused(deps.storage, &nft_address)?;
   
     let collection_info = COLLECTIONINFO.may_load(deps.storage, &nft_address)?;
     if collection_info == None{
         return Err(ContractError::WrongNFTContractError {  })
     }
-    let collection_info = collection_info.unwrap();
-    let off = OFFERINGS.load(deps.storage, (&nft_address, &offering_id))?;
-
-    let amount= info
-        .funds
-        .iter()
-        .find(|c| c.denom == off.list_price.denom)
-        .map(|c| Uint128::from(c.amount))
-        .unwrap_or_else(Uint128::zero);
-
-    if off.list_price.amount!=amount{
-        return Err(ContractError::NotEnoughFunds {  })
+    let id = offering_key(&offering_id)?;
+    let off = offerings().load(deps.storage, (&nft_address, id))?;
+    if matches!(token_id, Some(token_id) if token_id != off.token_id) {
+        return Err(ContractError::TokenIdMismatch {});
     }
-    if collection_info.offering_id == 1{    
-          OFFERINGS.remove( deps.storage, (&nft_address,&offering_id));
-          COLLECTIONINFO.update(deps.storage, &nft_address,
-             |collection_info|->StdResult<_>{
-                    let mut collection_info = collection_info.unwrap();
-                    collection_info.offering_id = 0;
-                    Ok(collection_info)
-             })?;
+    if off.is_expired(env.block.time.seconds()) {
+        return Err(ContractError::OfferingExpired {});
     }
 
-    else{
-        let crr_offering_id = collection_info.offering_id;
-        let offering = OFFERINGS.may_load(deps.storage, (&nft_address,&crr_offering_id.to_string()))?;
-        if offering!=None{
-            OFFERINGS.save(deps.storage, (&nft_address,&offering_id.to_string()), &offering.unwrap())?;
-           
-            COLLECTIONINFO.update(deps.storage, &nft_address,
-                |collection_info|->StdResult<_>{
-                        let mut collection_info = collection_info.unwrap();
-                        collection_info.offering_id =collection_info.offering_id-1;
-                        Ok(collection_info)
-                })?;
-        OFFERINGS.remove( deps.storage, (&nft_address,&crr_offering_id.to_string()));
-  
-         }
-    }
-    
-    let members = MEMBERS.load(deps.storage,&nft_address)?;
-    
-    let mut messages:Vec<CosmosMsg> = vec![];
-    for user in members{
-        messages.push(CosmosMsg::Bank(BankMsg::Send {
-                to_address: user.address,
-                amount:vec![Coin{
-                    denom:off.clone().list_price.denom,
-                    amount:amount*collection_info.royalty_portion*user.portion
-                }]
-        }))
-    }
-   
-    let price_info = PRICEINFO.may_load(deps.storage,&nft_address)?;
-   if price_info == None{
-        PRICEINFO.save(deps.storage,&nft_address,&PriceInfo {
-            total_hope:Uint128::new(0) ,
-            total_juno: amount })?;
-       }
-    else{
-        PRICEINFO.update(deps.storage,&nft_address,
-        |price_info|->StdResult<_>{
-            let mut price_info = price_info.unwrap();
-            price_info.total_juno = price_info.total_juno + amount;
-            Ok(price_info)
-        })?;}
-    
-     let sale_id = collection_info.sale_id+1;
-
-    SALEHISTORY.save(deps.storage, (&nft_address,&sale_id.to_string()),&SaleInfo {
-         from:off.seller.clone(), 
-         to: info.sender.to_string(), 
-         denom: off.list_price.denom.clone(),
-         amount: amount, 
-         time: env.block.time.seconds(),
-         nft_address:nft_address.clone(),
-         token_id:off.token_id.clone()         
-        })?;
-
-    COLLECTIONINFO.update(deps.storage, &nft_address, 
-        |collection_info|->StdResult<_>{
-            let mut collection_info = collection_info.unwrap();
-            collection_info.sale_id = sale_id;
-            Ok(collection_info)
-        }
-    )?;
+    let amount = native_amount(&info, &off.list_price.info)?;
 
+    let price = off.current_price(env.block.time.seconds());
+    check_payment(&off, price, amount)?;
+
+    remove_offering(deps.storage, &nft_address, id)?;
     
-    let tvl = TVL.may_load(deps.storage, (&nft_address,&off.list_price.denom))?;
-    let crr_tvl:Uint128;
-    if tvl == None {
-        crr_tvl = off.list_price.amount;
-    }
-    else {
-        crr_tvl = tvl.unwrap()+off.list_price.amount;
+    let mut response = settle_sale(deps, "buy_nft", SaleInfo {
+        from: off.seller,
+        to: info.sender.to_string(),
+        denom: off.list_price.info.to_string(),
+        amount: price,
+        time: env.block.time.seconds(),
+        nft_address,
+        token_id: off.token_id
+    }, &off.list_price.info)?;
+    if amount > price {
+        response = response.add_message(Asset { info: off.list_price.info, amount: amount - price }.into_msg(info.sender)?);
     }
 
-    TVL.save(deps.storage,( &nft_address,&off.list_price.denom), &crr_tvl)?;
-
-  
-    Ok(Response::new()
-        .add_message(CosmosMsg::Wasm(WasmMsg::Execute {
-                contract_addr: nft_address.to_string(),
-                funds: vec![],
-                msg: to_binary(&Cw721ExecuteMsg::TransferNft {
-                    recipient: info.sender.to_string(),
-                    token_id: off.token_id.clone(),
-            })?,
-        }))
-        .add_message(CosmosMsg::Bank(BankMsg::Send {
-                to_address: off.seller,
-                amount:vec![Coin{
-                    denom:off.list_price.denom,
-                    amount:amount*(Decimal::one()-collection_info.royalty_portion)
-                }]
-        }))
-        .add_messages(messages)
-)
+    Ok(response.add_attribute("offering_id", offering_id))
 }
 
-fn execute_withdraw(
+/// Records a completed sale and returns the nft transfer, seller payment, royalty and fee messages,
+/// all paid in `asset_info`.
+fn settle_sale(
     deps: DepsMut,
-    _env:Env,
-    info: MessageInfo,
-    offering_id: String,
-    nft_address:String
+    action: &str,
+    sale: SaleInfo,
+    asset_info: &AssetInfo
 ) -> Result<Response, ContractError> {
-    let off = OFFERINGS.load(deps.storage,(&nft_address,&offering_id))?;
-   // let state = CONFIG.load(deps.storage)?;
+    let state = CONFIG.load(deps.storage)?;
+    let mut collection_info = COLLECTIONINFO.may_load(deps.storage, &sale.nft_address)?
+        .ok_or(ContractError::WrongNFTContractError {})?;
+    // Tiers are reached with the volume traded before this sale
+    let fee = effective_fee(deps.storage, &state, &collection_info, &sale.denom)?;
+    let fee_amount = sale.amount * fee;
+    let (royalties, seller_share) = royalty_payouts(deps.as_ref(), &collection_info, &sale, sale.amount - fee_amount)?;
 
-    let collection_info = COLLECTIONINFO.may_load(deps.storage, &nft_address)?;
-    if collection_info == None{
-        return Err(ContractError::WrongNFTContractError {  })
-    }
-   let collection_info = collection_info.unwrap();
+    let tvl = TVL.may_load(deps.storage, (&sale.nft_address, &sale.denom))?.unwrap_or_default();
+    TVL.save(deps.storage, (&sale.nft_address, &sale.denom), &(tvl + sale.amount))?;
 
-   if collection_info.offering_id == 1{    
-          OFFERINGS.remove( deps.storage, (&nft_address,&offering_id));
-          COLLECTIONINFO.update(deps.storage, &nft_address,
-             |collection_info|->StdResult<_>{
-                    let mut collection_info = collection_info.unwrap();
-                    collection_info.offering_id = 0;
-                    Ok(collection_info)
-             })?;
-    }
+    record_sale(deps.storage, &sale, asset_info)?;
+    DEPOSITS.remove(deps.storage, (&sale.nft_address, &sale.token_id));
 
-    else{
-        let crr_offering_id = collection_info.offering_id;
-        let offering = OFFERINGS.may_load(deps.storage, (&nft_address,&crr_offering_id.to_string()))?;
-        if offering!=None{
-            OFFERINGS.save(deps.storage, (&nft_address,&offering_id.to_string()), &offering.unwrap())?;
-           
-            COLLECTIONINFO.update(deps.storage, &nft_address,
-                |collection_info|->StdResult<_>{
-                        let mut collection_info = collection_info.unwrap();
-                        collection_info.offering_id =collection_info.offering_id-1;
-                        Ok(collection_info)
-                })?;
-        OFFERINGS.remove( deps.storage, (&nft_address,&crr_offering_id.to_string()));
-  
-         }
-    }
+    collection_info.sale_id += 1;
+    COLLECTIONINFO.save(deps.storage, &sale.nft_address, &collection_info)?;
+    sale_history().save(deps.storage, (&sale.nft_address, &collection_info.sale_id.to_string()), &sale)?;
 
-    if off.seller == info.sender.to_string(){
+    let seller_amount = seller_share
+        .checked_sub(fee_amount)
+        .map_err(StdError::from)?;
 
-        Ok(Response::new()
-            .add_message(CosmosMsg::Wasm(WasmMsg::Execute {
-                contract_addr: nft_address.to_string(),
-                funds: vec![],
-                msg: to_binary(&Cw721ExecuteMsg::TransferNft {
-                    recipient: deps.api.addr_validate(&off.seller)?.to_string(),
-                    token_id: off.token_id.clone(),
+    let payment = |recipient: &str, amount: Uint128| {
+        Asset { info: asset_info.clone(), amount }.into_msg(Addr::unchecked(recipient))
+    };
+    let mut messages: Vec<CosmosMsg> = vec![
+        CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: sale.nft_address.clone(),
+            funds: vec![],
+            msg: to_binary(&Cw721ExecuteMsg::TransferNft {
+                recipient: sale.to.clone(),
+                token_id: sale.token_id.clone(),
             })?,
-        }))
-    )
+        }),
+    ];
+    // Zero amount transfers are rejected by the bank module and cw20 contracts
+    if !seller_amount.is_zero() {
+        messages.push(payment(&sale.from, seller_amount)?);
     }
-    else {
-        return Err(ContractError::Unauthorized {});
+    for (recipient, amount) in royalties {
+        if !amount.is_zero() {
+            messages.push(payment(&recipient, amount)?);
+        }
+    }
+    if !fee_amount.is_zero() {
+        messages.push(payment(&state.fee_collector, fee_amount)?);
     }
-    
-}
 
+    Ok(Response::new()
+        .add_messages(messages)
+        .add_attribute("action", action)
+        .add_attribute("collection", sale.nft_address.clone())
+        .add_attribute("token_id", sale.token_id.clone())
+        .add_attribute("sale_id", collection_info.sale_id.to_string())
+        .add_attribute("seller", sale.from.clone())
+        .add_attribute("buyer", sale.to.clone())
+        .add_attribute("price", Asset { info: asset_info.clone(), amount: sale.amount }.to_string())
+        .add_attribute("royalty", sale.amount - seller_share)
+        .add_attribute("fee_rate", fee.to_string())
+        .add_attribute("fee", fee_amount))
+}
 
-fn execute_add_collection(
-    deps: DepsMut,
-    _env:Env,
-    info: MessageInfo,
-    royalty_potion: Decimal,
-    members: Vec<UserInfo>,
-    nft_address:String,
-    offering_id:u64,
-    sale_id:u64
-)->Result<Response,ContractError>{
+/// Adds a sale to the collection and marketplace-wide statistics
+fn record_sale(storage: &mut dyn Storage, sale: &SaleInfo, asset_info: &AssetInfo) -> StdResult<()> {
+    let denom = asset_info.to_string();
+    VOLUMES.update(storage, (&sale.nft_address, &denom), |volume| add_volume(volume, asset_info, sale.amount))?;
+    GLOBAL_VOLUMES.update(storage, &denom, |volume| add_volume(volume, asset_info, sale.amount))?;
+
+    let new_buyer = !BUYERS.has(storage, &sale.to);
+    let new_seller = !SELLERS.has(storage, &sale.from);
+    BUYERS.save(storage, &sale.to, &true)?;
+    SELLERS.save(storage, &sale.from, &true)?;
+    update_global_stats(storage, |stats| {
+        stats.total_sales += 1;
+        stats.unique_buyers += new_buyer as u64;
+        stats.unique_sellers += new_seller as u64;
+    })
+}
 
-    let state = CONFIG.load(deps.storage)?;
+pub(crate) fn add_volume(volume: Option<VolumeInfo>, asset_info: &AssetInfo, amount: Uint128) -> StdResult<VolumeInfo> {
+    let mut volume = volume.unwrap_or(VolumeInfo {
+        info: asset_info.clone(),
+        amount: Uint128::zero(),
+        sales: 0
+    });
+    volume.amount += amount;
+    volume.sales += 1;
+    Ok(volume)
+}
 
-    deps.api.addr_validate(&nft_address)?;
+fn update_global_stats(storage: &mut dyn Storage, action: impl FnOnce(&mut GlobalStats)) -> StdResult<()> {
+    let mut stats = GLOBAL_STATS.may_load(storage)?.unwrap_or_default();
+    action(&mut stats);
+    GLOBAL_STATS.save(storage, &stats)
+}
 
-    if info.sender.to_string() != state.owner{
-        return Err(ContractError::Unauthorized {});
+/// Returns the royalty payments of a sale and the part of the price left to the seller before fees.
+/// Royalties are capped at `available`, the price left after the marketplace fee.
+fn royalty_payouts(
+    deps: Deps,
+    collection_info: &CollectionInfo,
+    sale: &SaleInfo,
+    available: Uint128
+) -> StdResult<(Vec<(String, Uint128)>, Uint128)> {
+    if collection_info.royalty_mode != RoyaltyMode::Static {
+        let query = Cw2981QueryMsg::Extension {
+            msg: RoyaltyQueryMsg::RoyaltyInfo {
+                token_id: sale.token_id.clone(),
+                sale_price: sale.amount,
+            },
+        };
+        // Contracts without the extension or reporting an invalid recipient fall back to the static split
+        let royalty = deps.querier.query_wasm_smart::<RoyaltiesInfoResponse>(&sale.nft_address, &query).ok()
+            .filter(|royalty| deps.api.addr_validate(&royalty.address).is_ok());
+        if let Some(royalty) = royalty {
+            let mut royalty_amount = royalty.royalty_amount.min(available);
+            if collection_info.royalty_mode == RoyaltyMode::Cw2981Capped {
+                royalty_amount = royalty_amount.min(sale.amount * collection_info.royalty_portion);
+            }
+            let payouts = if royalty_amount.is_zero() {
+                vec![]
+            } else {
+                vec![(royalty.address, royalty_amount)]
+            };
+            return Ok((payouts, sale.amount - royalty_amount));
+        }
     }
-    
-    let mut sum_portion = Decimal::zero();
 
-    for item in members.clone() {
-        sum_portion = sum_portion + item.portion;
-        deps.api.addr_validate(&item.address)?;
-    }
+    let members = MEMBERS.load(deps.storage, &sale.nft_address)?;
+    let mut remaining = available;
+    let payouts: Vec<(String, Uint128)> = members.into_iter()
+        .map(|user| {
+            let amount = (sale.amount * collection_info.royalty_portion * user.portion).min(remaining);
+            remaining -= amount;
+            (user.address, amount)
+        })
+        .collect();
+    // The seller keeps at least the fee when the royalty had to be capped
+    let seller_share = (sale.amount * (Decimal::one() - collection_info.royalty_portion)).max(sale.amount - available);
+    Ok((payouts, seller_share))
+}
 
-    if sum_portion != Decimal::one(){
-        return Err(ContractError::WrongPortionError { })
+/// Resolves the fee rate charged on a sale in `denom`: the collection override or the
+/// marketplace fee, reduced by the highest volume tier the collection has reached.
+fn effective_fee(
+    storage: &dyn Storage,
+    state: &State,
+    collection_info: &CollectionInfo,
+    denom: &str
+) -> StdResult<Decimal> {
+    // An unset collector (state saved before fees existed) charges no fee
+    if state.fee_collector.is_empty() {
+        return Ok(Decimal::zero());
     }
+    let fee = collection_info.fee.unwrap_or(state.fee);
 
-    MEMBERS.save(deps.storage,&nft_address, &members)?;
-    COLLECTIONINFO.save(deps.storage,&nft_address,&CollectionInfo{
-        nft_address:nft_address.clone(),
-        offering_id:offering_id,
-        sale_id:sale_id,
-        royalty_portion:royalty_potion
-    })?;
-    Ok(Response::default())
-}
+    let volume = TVL.may_load(storage, (&collection_info.nft_address, denom))?.unwrap_or_default();
+    let discount = state.fee_tiers.iter()
+        .filter(|tier| tier.denom == denom && volume >= tier.min_volume)
+        .max_by_key(|tier| tier.min_volume)
+        .map(|tier| tier.discount)
+        .unwrap_or_else(Decimal::zero);
 
+    Ok(fee * (Decimal::one() - discount))
+}
 
-fn execute_update_collection(
-    deps: DepsMut,
-    _env:Env,
-    info: MessageInfo,
-    royalty_potion: Decimal,
-    members: Vec<UserInfo>,
-    nft_address:String
-)->Result<Response,ContractError>{
+/// Fails while trading is paused globally or on `nft_address`.
+fn assert_not_paused(storage: &dyn Storage, nft_address: &str) -> Result<(), ContractError> {
+    if PAUSED.may_load(storage)?.unwrap_or(false) || PAUSED_COLLECTIONS.has(storage, nft_address) {
+        return Err(ContractError::Paused {});
+    }
+    Ok(())
+}
 
+/// Guards the admin functions no role grants.
+fn assert_owner(deps: Deps, sender: &Addr) -> Result<(), ContractError> {
     let state = CONFIG.load(deps.storage)?;
-
-    deps.api.addr_validate(&nft_address)?;
-
-    if info.sender.to_string() != state.owner{
+    if *sender != state.owner {
         return Err(ContractError::Unauthorized {});
     }
+    Ok(())
+}
 
-    let collection_info = COLLECTIONINFO.may_load(deps.storage,&nft_address)?;
-    if collection_info == None{
-        return Err(ContractError::WrongCollection {  })
+/// The owner holds every role, anyone else needs `role` granted.
+fn assert_role(deps: Deps, sender: &Addr, role: Role) -> Result<(), ContractError> {
+    let state = CONFIG.load(deps.storage)?;
+    if *sender == state.owner || ROLES.has(deps.storage, (role.as_str(), sender.as_str())) {
+        return Ok(());
     }
-    let collection_info = collection_info.unwrap();
-
-    let mut sum_portion = Decimal::zero();
+    Err(ContractError::MissingRole { role })
+}
 
-    for item in members.clone() {
-        sum_portion = sum_portion + item.portion;
-        deps.api.addr_validate(&item.address)?;
+fn validate_fee(fee: Decimal) -> Result<(), ContractError> {
+    if fee >= Decimal::one() {
+        return Err(ContractError::InvalidFee {});
     }
+    Ok(())
+}
 
-    if sum_portion != Decimal::one(){
-        return Err(ContractError::WrongPortionError { })
+/// Fixed-price offerings must be paid exactly, Dutch auctions accept overpayment
+/// since the price keeps falling until the purchase executes and the difference is refunded.
+fn check_payment(off: &Offering, price: Uint128, amount: Uint128) -> Result<(), ContractError> {
+    if amount < price || (off.dutch_auction.is_none() && amount != price) {
+        return Err(ContractError::NotEnoughFunds {});
     }
+    Ok(())
+}
 
-    MEMBERS.save(deps.storage,&nft_address, &members)?;
-    COLLECTIONINFO.save(deps.storage,&nft_address,&CollectionInfo{
-        nft_address:nft_address.clone(),
-        offering_id:collection_info.offering_id,
-        royalty_portion:royalty_potion,
-        sale_id:collection_info.sale_id
-    })?;
-    Ok(Response::default())
+/// Amount of the native `asset_info` sent along, cw20 payments only go through the token contract.
+fn native_amount(info: &MessageInfo, asset_info: &AssetInfo) -> Result<Uint128, ContractError> {
+    match asset_info {
+        AssetInfo::NativeToken { denom } => Ok(info
+            .funds
+            .iter()
+            .find(|c| c.denom == *denom)
+            .map(|c| c.amount)
+            .unwrap_or_else(Uint128::zero)),
+        AssetInfo::Token { .. } => Err(ContractError::WrongTokenContractError {}),
+    }
 }
 
+/// cw20 prices must be in a token registered with `AddTokenAddress`, or buyers could not pay them.
+fn validate_price(deps: Deps, price: &Asset) -> Result<(), ContractError> {
+    if let AssetInfo::Token { contract_addr } = &price.info {
+        if !TOKENADDRESS.has(deps.storage, contract_addr) {
+            return Err(ContractError::WrongTokenContractError {});
+        }
+    }
+    Ok(())
+}
 
-fn execute_token_address(
+fn execute_start_auction(
     deps: DepsMut,
-    _env:Env,
+    env:Env,
     info: MessageInfo,
-    symbol:String,
-    address: String,
+    rcv_msg: Cw721ReceiveMsg,
+    msg: StartAuction
 ) -> Result<Response, ContractError> {
-    let  state = CONFIG.load(deps.storage)?;
-    deps.api.addr_validate(&address)?;
+    let nft_address = info.sender.to_string();
+    let mut collection_info = COLLECTIONINFO.load(deps.storage, &nft_address)?;
 
-     if info.sender.to_string() != state.owner{
-        return Err(ContractError::Unauthorized {});
+    if msg.end_time <= env.block.time.seconds() {
+        return Err(ContractError::InvalidEndTime {});
     }
-    
-    TOKENADDRESS.save(deps.storage,&address,&symbol)?;
+    validate_price(deps.as_ref(), &msg.reserve_price)?;
 
-    CONFIG.save(deps.storage, &state)?;
-    Ok(Response::default())
-}
+    collection_info.auction_id += 1;
+    COLLECTIONINFO.save(deps.storage, &nft_address, &collection_info)?;
 
+    let auction = Auction {
+        nft_address: nft_address.clone(),
+        token_id: rcv_msg.token_id,
+        seller: deps.api.addr_validate(&rcv_msg.sender)?.to_string(),
+        reserve_price: msg.reserve_price,
+        min_increment: msg.min_increment,
+        end_time: msg.end_time,
+        highest_bid: None,
+    };
+    auctions().save(deps.storage, (&nft_address, &collection_info.auction_id.to_string()), &auction)?;
+    DEPOSITS.save(deps.storage, (&nft_address, &auction.token_id), &auction.seller)?;
 
-fn execute_fix_nft(
-    deps: DepsMut,
-    _env:Env,
+    Ok(Response::new()
+        .add_attribute("action", "start_auction")
+        .add_attribute("collection", nft_address)
+        .add_attribute("token_id", auction.token_id)
+        .add_attribute("auction_id", collection_info.auction_id.to_string())
+        .add_attribute("seller", auction.seller)
+        .add_attribute("price", auction.reserve_price.to_string())
+        .add_attribute("end_time", auction.end_time.to_string()))
+}
+
+fn execute_place_bid(
+    deps: DepsMut,
+    env:Env,
     info: MessageInfo,
-    address: String,
-    token_id:String
+    nft_address:String,
+    auction_id:String
 ) -> Result<Response, ContractError> {
-    let state = CONFIG.load(deps.storage)?;
-    deps.api.addr_validate(&address)?;
-    if state.owner != info.sender.to_string() {
-        return Err(ContractError::Unauthorized {});
+    let auction = auctions().load(deps.storage, (&nft_address, &auction_id))?;
+    let amount = native_amount(&info, &auction.reserve_price.info)?;
+
+    let bid = Bid {
+        bidder: info.sender.to_string(),
+        amount,
+    };
+    place_bid(deps, env, nft_address, auction_id, bid)
+}
+
+/// Replaces the highest bid of an auction with `bid`, refunding the outbid bidder.
+fn place_bid(
+    deps: DepsMut,
+    env:Env,
+    nft_address:String,
+    auction_id:String,
+    bid: Bid
+) -> Result<Response, ContractError> {
+    assert_not_paused(deps.storage, &nft_address)?;
+    let mut auction = auctions().load(deps.storage, (&nft_address, &auction_id))?;
+
+    if env.block.time.seconds() >= auction.end_time {
+        return Err(ContractError::AuctionEnded {});
+    }
+    if bid.amount.is_zero() {
+        return Err(ContractError::NotEnoughFunds {});
     }
 
-    Ok(Response::new()
-        .add_message(CosmosMsg::Wasm(WasmMsg::Execute {
-                contract_addr: address,
-                funds: vec![],
-                msg: to_binary(&Cw721ExecuteMsg::TransferNft {
-                    recipient: info.sender.to_string(),
-                    token_id: token_id.clone(),
-            })?,
-        })))
+    let mut messages: Vec<CosmosMsg> = vec![];
+    match auction.highest_bid {
+        Some(highest_bid) => {
+            if bid.amount <= highest_bid.amount || bid.amount < highest_bid.amount + auction.min_increment {
+                return Err(ContractError::BidTooLow {});
+            }
+            messages.push(Asset { info: auction.reserve_price.info.clone(), amount: highest_bid.amount }
+                .into_msg(Addr::unchecked(highest_bid.bidder))?);
+        }
+        None => {
+            if bid.amount < auction.reserve_price.amount {
+                return Err(ContractError::BidTooLow {});
+            }
+        }
+    }
+
+    let response = Response::new()
+        .add_messages(messages)
+        .add_attribute("action", "place_bid")
+        .add_attribute("collection", nft_address.clone())
+        .add_attribute("token_id", auction.token_id.clone())
+        .add_attribute("auction_id", auction_id.clone())
+        .add_attribute("bidder", bid.bidder.clone())
+        .add_attribute("price", Asset { info: auction.reserve_price.info.clone(), amount: bid.amount }.to_string());
+
+    auction.highest_bid = Some(bid);
+    auctions().save(deps.storage, (&nft_address, &auction_id), &auction)?;
+
+    Ok(response)
 }
 
+fn execute_settle_auction(
+    deps: DepsMut,
+    env:Env,
+    _info: MessageInfo,
+    nft_address:String,
+    auction_id:String
+) -> Result<Response, ContractError> {
+    let auction = auctions().load(deps.storage, (&nft_address, &auction_id))?;
+
+    if env.block.time.seconds() < auction.end_time {
+        return Err(ContractError::AuctionNotEnded {});
+    }
+    // Only sales halt while paused, unsold nfts can still go back to their sellers
+    if auction.highest_bid.is_some() {
+        assert_not_paused(deps.storage, &nft_address)?;
+    }
+    auctions().remove(deps.storage, (&nft_address, &auction_id))?;
+
+    match auction.highest_bid {
+        Some(highest_bid) => {
+            let response = settle_sale(deps, "settle_auction", SaleInfo {
+                from: auction.seller,
+                to: highest_bid.bidder,
+                denom: auction.reserve_price.info.to_string(),
+                amount: highest_bid.amount,
+                time: env.block.time.seconds(),
+                nft_address,
+                token_id: auction.token_id
+            }, &auction.reserve_price.info)?;
+            Ok(response.add_attribute("auction_id", auction_id))
+        }
+        // Nobody met the reserve price, the nft goes back to the seller
+        None => {
+            DEPOSITS.remove(deps.storage, (&nft_address, &auction.token_id));
+            Ok(Response::new()
+                .add_message(CosmosMsg::Wasm(WasmMsg::Execute {
+                    contract_addr: nft_address.clone(),
+                    funds: vec![],
+                    msg: to_binary(&Cw721ExecuteMsg::TransferNft {
+                        recipient: auction.seller.clone(),
+                        token_id: auction.token_id.clone(),
+                    })?,
+                }))
+                .add_attribute("action", "settle_auction")
+                .add_attribute("collection", nft_address)
+                .add_attribute("token_id", auction.token_id)
+                .add_attribute("auction_id", auction_id)
+                .add_attribute("seller", auction.seller))
+        }
+    }
+}
 
-fn execute_migrate(
+fn execute_make_offer(
     deps: DepsMut,
-    _env:Env,
+    env:Env,
     info: MessageInfo,
-    address: String,
-    dest:String,
-    token_ids:Vec<String>
+    nft_address:String,
+    token_id:String,
+    price:Asset,
+    expires:u64
 ) -> Result<Response, ContractError> {
-    let state = CONFIG.load(deps.storage)?;
-    deps.api.addr_validate(&address)?;
-     deps.api.addr_validate(&dest)?;
-
-    if state.owner != info.sender.to_string() {
-        return Err(ContractError::Unauthorized {});
+    // cw20 offers are escrowed through the token contract
+    let amount = native_amount(&info, &price.info)?;
+    if amount != price.amount {
+        return Err(ContractError::NotEnoughFunds {});
     }
 
-    let mut messages:Vec<CosmosMsg> = vec![];
+    let offer = TokenOffer {
+        nft_address,
+        token_id,
+        bidder: info.sender.to_string(),
+        price,
+        expires,
+    };
+    make_offer(deps, env, offer)
+}
 
-    for token_id in token_ids{
-        messages.push(CosmosMsg::Wasm(WasmMsg::Execute {
-                contract_addr: address.clone(),
-                funds: vec![],
-                msg: to_binary(&Cw721ExecuteMsg::TransferNft {
-                    recipient: dest.clone(),
-                    token_id: token_id.clone(),
-            })?,
-        }))
+/// Stores an escrowed offer, refunding the previous offer of the same bidder on that token.
+fn make_offer(
+    deps: DepsMut,
+    env:Env,
+    offer: TokenOffer
+) -> Result<Response, ContractError> {
+    assert_not_paused(deps.storage, &offer.nft_address)?;
+    if !COLLECTIONINFO.has(deps.storage, &offer.nft_address) {
+        return Err(ContractError::WrongNFTContractError {});
+    }
+    if offer.expires <= env.block.time.seconds() {
+        return Err(ContractError::InvalidExpiration {});
+    }
+    if offer.price.amount.is_zero() {
+        return Err(ContractError::NotEnoughFunds {});
+    }
+
+    let key = (offer.nft_address.as_str(), offer.token_id.as_str(), offer.bidder.as_str());
+    let mut messages: Vec<CosmosMsg> = vec![];
+    if let Some(previous) = token_offers().may_load(deps.storage, key)? {
+        messages.push(previous.price.into_msg(Addr::unchecked(previous.bidder))?);
     }
+    token_offers().save(deps.storage, key, &offer)?;
 
     Ok(Response::new()
-        .add_messages(messages))
+        .add_messages(messages)
+        .add_attribute("action", "make_offer")
+        .add_attribute("collection", offer.nft_address.clone())
+        .add_attribute("token_id", offer.token_id.clone())
+        .add_attribute("bidder", offer.bidder.clone())
+        .add_attribute("price", offer.price.to_string()))
 }
 
-
-fn execute_change_owner(
+fn execute_cancel_offer(
     deps: DepsMut,
     _env:Env,
     info: MessageInfo,
-    address: String,
+    nft_address:String,
+    token_id:String
 ) -> Result<Response, ContractError> {
-    let mut state = CONFIG.load(deps.storage)?;
+    let key = (nft_address.as_str(), token_id.as_str(), info.sender.as_str());
+    let offer = token_offers().load(deps.storage, key)?;
+    token_offers().remove(deps.storage, key)?;
 
-    if state.owner != info.sender.to_string() {
-        return Err(ContractError::Unauthorized {});
+    Ok(Response::new()
+        .add_message(offer.price.into_msg(Addr::unchecked(&offer.bidder))?)
+        .add_attribute("action", "cancel_offer")
+        .add_attribute("collection", nft_address)
+        .add_attribute("token_id", token_id)
+        .add_attribute("bidder", offer.bidder))
+}
+
+fn execute_accept_offer(
+    deps: DepsMut,
+    env:Env,
+    info: MessageInfo,
+    rcv_msg: Cw721ReceiveMsg,
+    bidder: String
+) -> Result<Response, ContractError> {
+    let nft_address = info.sender.to_string();
+    let key = (nft_address.as_str(), rcv_msg.token_id.as_str(), bidder.as_str());
+    let offer = token_offers().load(deps.storage, key)?;
+
+    if offer.expires <= env.block.time.seconds() {
+        return Err(ContractError::OfferExpired {});
     }
-    deps.api.addr_validate(&address)?;
-    state.owner = address;
-    CONFIG.save(deps.storage,&state)?;
-    Ok(Response::default())
+    token_offers().remove(deps.storage, key)?;
+
+    let response = settle_sale(deps, "accept_offer", SaleInfo {
+        from: rcv_msg.sender,
+        to: offer.bidder,
+        denom: offer.price.info.to_string(),
+        amount: offer.price.amount,
+        time: env.block.time.seconds(),
+        nft_address,
+        token_id: rcv_msg.token_id
+    }, &offer.price.info)?;
+
+    Ok(response)
 }
 
-fn execute_set_tvl(
+fn execute_make_collection_offer(
     deps: DepsMut,
-    _env:Env,
+    env:Env,
     info: MessageInfo,
-    address: String,
-    tvls: Vec<TvlInfo>,
+    nft_address:String,
+    price:Asset,
+    quantity:Option<u64>,
+    expires:u64
 ) -> Result<Response, ContractError> {
-    let  state = CONFIG.load(deps.storage)?;
+    let amount = native_amount(&info, &price.info)?;
 
-    if state.owner != info.sender.to_string() {
-        return Err(ContractError::Unauthorized {});
+    let quantity = quantity.unwrap_or(1);
+    if quantity == 0 {
+        return Err(ContractError::InvalidQuantity {});
     }
-   
-    for tvl in tvls{
-        TVL.save(deps.storage, (&address,&tvl.denom), &tvl.amount)?;
+    if amount != price.amount * Uint128::from(quantity) {
+        return Err(ContractError::NotEnoughFunds {});
     }
 
-    Ok(Response::default())
+    let offer = CollectionOffer {
+        nft_address,
+        bidder: info.sender.to_string(),
+        price,
+        quantity,
+        expires,
+    };
+    make_collection_offer(deps, env, offer)
 }
 
-fn execute_set_offerings(
+/// Stores an escrowed collection offer, refunding what is left of the bidder's previous one.
+fn make_collection_offer(
     deps: DepsMut,
-    _env:Env,
-    info:MessageInfo,
-    address: String,
-    offerings:Vec<QueryOfferingsResult>
+    env:Env,
+    offer: CollectionOffer
 ) -> Result<Response, ContractError> {
-    let  state = CONFIG.load(deps.storage)?;
-
-    if state.owner != info.sender.to_string() {
-        return Err(ContractError::Unauthorized {});
+    assert_not_paused(deps.storage, &offer.nft_address)?;
+    if !COLLECTIONINFO.has(deps.storage, &offer.nft_address) {
+        return Err(ContractError::WrongNFTContractError {});
     }
-    
-    for offering in offerings{
-        let crr_offering = Offering{
-            token_id:offering.token_id,
-            seller:offering.seller,
-            list_price:offering.list_price
-        };
-        OFFERINGS.save(deps.storage, (&address,&offering.id), &crr_offering)?;
+    if offer.expires <= env.block.time.seconds() {
+        return Err(ContractError::InvalidExpiration {});
     }
-   
-    Ok(Response::default())
+    if offer.price.amount.is_zero() {
+        return Err(ContractError::NotEnoughFunds {});
+    }
+
+    let key = (offer.nft_address.as_str(), offer.bidder.as_str());
+    let mut messages: Vec<CosmosMsg> = vec![];
+    if let Some(previous) = COLLECTION_OFFERS.may_load(deps.storage, key)? {
+        messages.push(refund_collection_offer(&previous)?);
+    }
+    COLLECTION_OFFERS.save(deps.storage, key, &offer)?;
+
+    Ok(Response::new()
+        .add_messages(messages)
+        .add_attribute("action", "make_collection_offer")
+        .add_attribute("collection", offer.nft_address.clone())
+        .add_attribute("bidder", offer.bidder.clone())
+        .add_attribute("price", offer.price.to_string())
+        .add_attribute("quantity", offer.quantity.to_string()))
 }
 
+/// Returns the escrow still held for the unfilled quantity of a collection offer.
+fn refund_collection_offer(offer: &CollectionOffer) -> StdResult<CosmosMsg> {
+    Asset { info: offer.price.info.clone(), amount: offer.price.amount * Uint128::from(offer.quantity) }
+        .into_msg(Addr::unchecked(&offer.bidder))
+}
 
-fn execute_history(
+fn execute_cancel_collection_offer(
     deps: DepsMut,
     _env:Env,
-    info:MessageInfo,
-    address: String,
-    histories:Vec<SaleInfo>
+    info: MessageInfo,
+    nft_address:String
 ) -> Result<Response, ContractError> {
-    let  state = CONFIG.load(deps.storage)?;
-
-    if state.owner != info.sender.to_string() {
-        return Err(ContractError::Unauthorized {});
-    }
+    let key = (nft_address.as_str(), info.sender.as_str());
+    let offer = COLLECTION_OFFERS.load(deps.storage, key)?;
+    COLLECTION_OFFERS.remove(deps.storage, key);
 
-    let mut count = 0;
-    
-    for history in histories{
-        count =  count+1;
-        SALEHISTORY.save(deps.storage, (&address,&count.to_string()), &history)?;
-    }
-   
-    Ok(Response::default())
+    Ok(Response::new()
+        .add_message(refund_collection_offer(&offer)?)
+        .add_attribute("action", "cancel_collection_offer")
+        .add_attribute("collection", nft_address)
+        .add_attribute("bidder", offer.bidder))
 }
 
+fn execute_accept_collection_offer(
+    deps: DepsMut,
+    env:Env,
+    info: MessageInfo,
+    rcv_msg: Cw721ReceiveMsg,
+    bidder: String
+) -> Result<Response, ContractError> {
+    let nft_address = info.sender.to_string();
+    let key = (nft_address.as_str(), bidder.as_str());
+    let mut offer = COLLECTION_OFFERS.load(deps.storage, key)?;
 
-#[entry_point]
-pub fn query(deps: Deps, _env: Env, msg: QueryMsg) -> StdResult<Binary> {
-    match msg {
-        QueryMsg::GetStateInfo {} => to_binary(&query_state_info(deps)?),
-        QueryMsg::GetMembers {address} => to_binary(&query_get_members(deps,address)?),
-        QueryMsg::GetTradingInfo { address} => to_binary(&query_get_trading(deps,address)?),
-        QueryMsg::GetSaleHistory {address, id } => to_binary(&query_get_history(deps,address,id)?),
-        QueryMsg::GetCollectionInfo { address } =>to_binary(&query_collection_info(deps,address)?),
-        QueryMsg::GetOfferingId {address }=> to_binary(&query_get_ids(deps,address)?),
-        QueryMsg::GetOfferingPage { id,address }  => to_binary(&query_get_offering(deps,id,address)?),
-        QueryMsg::GetTvl { address, symbol }=> to_binary(&query_get_tvl(deps,address,symbol)?),
-        QueryMsg::GetTvlAll { address, symbols }=> to_binary(&query_all_tvl(deps,address,symbols)?)
+    if offer.expires <= env.block.time.seconds() {
+        return Err(ContractError::OfferExpired {});
+    }
+    offer.quantity -= 1;
+    if offer.quantity == 0 {
+        COLLECTION_OFFERS.remove(deps.storage, key);
+    } else {
+        COLLECTION_OFFERS.save(deps.storage, key, &offer)?;
     }
-}
 
-pub fn query_state_info(deps:Deps) -> StdResult<State>{
-    let state =  CONFIG.load(deps.storage)?;
-    Ok(state)
-}
+    let response = settle_sale(deps, "accept_collection_offer", SaleInfo {
+        from: rcv_msg.sender,
+        to: offer.bidder,
+        denom: offer.price.info.to_string(),
+        amount: offer.price.amount,
+        time: env.block.time.seconds(),
+        nft_address,
+        token_id: rcv_msg.token_id
+    }, &offer.price.info)?;
 
-pub fn query_collection_info(deps:Deps,address:String) -> StdResult<CollectionInfo>{
-    let collection_info =  COLLECTIONINFO.load(deps.storage,&address)?;
-    Ok(collection_info)
+    Ok(response.add_attribute("remaining", offer.quantity.to_string()))
 }
 
+fn execute_withdraw(
+    deps: DepsMut,
+    _env:Env,
+    info: MessageInfo,
+    offering_id: String,
+    nft_address:String
+) -> Result<Response, ContractError> {
+    let id = offering_key(&offering_id)?;
+    let off = offerings().load(deps.storage,(&nft_address,id))?;
+   // let state = CONFIG.load(deps.storage)?;
 
-pub fn query_get_tvl(deps:Deps,address:String,symbol:String) -> StdResult<Uint128>{
-    let tvl = TVL.may_load(deps.storage, (&address,&symbol))?;
-    if tvl == None{
-        Ok(Uint128::new(0))
+    let collection_info = COLLECTIONINFO.may_load(deps.storage, &nft_address)?;
+    if collection_info == None{
+        return Err(ContractError::WrongNFTContractError {  })
     }
-    else{
-        Ok(tvl.unwrap())
+    remove_offering(deps.storage, &nft_address, id)?;
+    DEPOSITS.remove(deps.storage, (&nft_address, &off.token_id));
+
+    if off.seller == info.sender.to_string(){
+
+        Ok(Response::new()
+            .add_message(CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: nft_address.to_string(),
+                funds: vec![],
+                msg: to_binary(&Cw721ExecuteMsg::TransferNft {
+                    recipient: deps.api.addr_validate(&off.seller)?.to_string(),
+                    token_id: off.token_id.clone(),
+            })?,
+        }))
+        .add_attribute("action", "withdraw_nft")
+        .add_attribute("collection", nft_address)
+        .add_attribute("token_id", off.token_id)
+        .add_attribute("offering_id", offering_id)
+        .add_attribute("seller", off.seller)
+    )
+    }
+    else {
+        return Err(ContractError::Unauthorized {});
     }
+    
 }
 
-pub fn query_all_tvl(deps:Deps,address:String,symbols:Vec<String>) -> StdResult<Vec<TvlInfo>>{
-    let mut empty:Vec<TvlInfo> = vec![];
-    for symbol in symbols
-    {
-        let tvl = TVL.may_load(deps.storage, (&address,&symbol))?;
-        if tvl == None{
-            empty.push(TvlInfo { denom: symbol, amount: Uint128::new(0) })
-        }
-        else{
-              empty.push(TvlInfo { denom: symbol, amount: tvl.unwrap() })
-         
-        }
+fn execute_update_offering(
+    deps: DepsMut,
+    _env:Env,
+    info: MessageInfo,
+    nft_address:String,
+    offering_id:String,
+    list_price:Asset
+) -> Result<Response, ContractError> {
+    let id = offering_key(&offering_id)?;
+    let mut off = offerings().load(deps.storage,(&nft_address,id))?;
+    if off.seller != info.sender {
+        return Err(ContractError::Unauthorized {});
     }
-    Ok(empty)
-}
+    // The price schedule of a Dutch auction is fixed once started
+    if off.dutch_auction.is_some() {
+        return Err(ContractError::DutchAuctionPrice {});
+    }
+    validate_price(deps.as_ref(), &list_price)?;
 
+    let old_price = off.list_price;
+    off.list_price = list_price;
+    offerings().save(deps.storage,(&nft_address,id),&off)?;
 
-pub fn query_get_members(deps:Deps,address:String) -> StdResult<Vec<UserInfo>>{
-    let members = MEMBERS.load(deps.storage,&address)?;
-    Ok(members)
+    Ok(Response::new()
+        .add_attribute("action", "update_offering")
+        .add_attribute("collection", nft_address)
+        .add_attribute("offering_id", offering_id)
+        .add_attribute("token_id", off.token_id)
+        .add_attribute("old_price", old_price.to_string())
+        .add_attribute("new_price", off.list_price.to_string())
+        .add_attribute("seller", off.seller))
 }
 
-pub fn query_get_trading(deps:Deps,address:String) -> StdResult<PriceInfo>{
-    let price_info = PRICEINFO.may_load(deps.storage,&address)?;
-    if price_info ==None{
-        Ok(PriceInfo{
-            total_hope:Uint128::new(0),
-            total_juno:Uint128::new(0)
-        })
+/// Returns an expired offering's nft to its seller, callable by anyone.
+fn execute_reclaim_expired(
+    deps: DepsMut,
+    env:Env,
+    _info: MessageInfo,
+    nft_address:String,
+    offering_id:String
+) -> Result<Response, ContractError> {
+    let id = offering_key(&offering_id)?;
+    let off = offerings().load(deps.storage,(&nft_address,id))?;
+    if !off.is_expired(env.block.time.seconds()) {
+        return Err(ContractError::NotExpired {});
     }
-    else{
-    Ok(price_info.unwrap())}
+
+    remove_offering(deps.storage, &nft_address, id)?;
+    DEPOSITS.remove(deps.storage, (&nft_address, &off.token_id));
+
+    Ok(Response::new()
+        .add_message(CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: nft_address.clone(),
+            funds: vec![],
+            msg: to_binary(&Cw721ExecuteMsg::TransferNft {
+                recipient: off.seller.clone(),
+                token_id: off.token_id.clone(),
+            })?,
+        }))
+        .add_attribute("action", "reclaim_expired")
+        .add_attribute("collection", nft_address)
+        .add_attribute("token_id", off.token_id)
+        .add_attribute("offering_id", offering_id)
+        .add_attribute("seller", off.seller))
 }
 
-// pub fn query_get_offerings(deps:Deps) -> StdResult<OfferingsResponse>{
-//     let res: StdResult<Vec<QueryOfferingsResult>> = OFFERINGS
-//         .range(deps.storage, None, None, Order::Ascending)
-//         .map(|kv_item| parse_offering(deps, kv_item  ))
-//         .collect();
-//     Ok(OfferingsResponse {
-//         offerings: res?, // Placeholder
-//     })
-// }
+/// Offering ids are decimal strings in messages and responses
+fn offering_key(offering_id: &str) -> StdResult<u64> {
+    offering_id.parse().map_err(|_| StdError::parse_err("u64", format!("invalid offering id {}", offering_id)))
+}
 
-// fn parse_offering(
-//     deps:Deps,
-//     item: StdResult<((String,String),Offering)>,
-// ) -> StdResult<QueryOfferingsResult> {
-//     item.and_then(|((address,k), offering)| {
-//         Ok(QueryOfferingsResult {
-//             id: k,
-//             token_id: offering.token_id,
-//             list_price: offering.list_price,
-//             seller: deps.api.addr_validate(&offering.seller)?.to_string(),
-//         })
-//     })
-// }
+fn remove_offering(storage: &mut dyn Storage, nft_address: &str, offering_id: u64) -> Result<(), ContractError> {
+    let mut collection_info = COLLECTIONINFO.may_load(storage, nft_address)?
+        .ok_or(ContractError::WrongNFTContractError {})?;
+    offerings().remove(storage, (nft_address, offering_id))?;
+    collection_info.num_offerings = collection_info.num_offerings.saturating_sub(1);
+    COLLECTIONINFO.save(storage, nft_address, &collection_info)?;
+    update_global_stats(storage, |stats| stats.active_listings = stats.active_listings.saturating_sub(1))?;
+    Ok(())
+}
 
 
-pub fn query_get_ids(deps:Deps,address: String) -> StdResult<Vec<String>>{
-     let token_id:StdResult<Vec<String>>  = OFFERINGS
-        .keys(deps.storage, None, None, Order::Ascending)
-        .filter(|keys|keys.as_ref().unwrap().0 == address.clone())
-        .map(|keys|parse_keys(deps, keys))
-        .collect();
-    Ok(token_id?)
+fn execute_add_collection(
+    deps: DepsMut,
+    _env:Env,
+    info: MessageInfo,
+    royalty_potion: Decimal,
+    members: Vec<UserInfo>,
+    nft_address:String,
+    offering_id:u64,
+    sale_id:u64,
+    fee:Option<Decimal>,
+    royalty_mode:Option<RoyaltyMode>
+)->Result<Response,ContractError>{
+
+    assert_role(deps.as_ref(), &info.sender, Role::Curator)?;
+
+    deps.api.addr_validate(&nft_address)?;
+    
+    let mut sum_portion = Decimal::zero();
+
+    for item in members.clone() {
+        sum_portion = sum_portion + item.portion;
+        deps.api.addr_validate(&item.address)?;
+    }
+
+    if sum_portion != Decimal::one(){
+        return Err(ContractError::WrongPortionError { })
+    }
+
+    if let Some(fee) = fee {
+        validate_fee(fee)?;
+    }
+
+    // Re-adding a collection must not reuse the ids of live offerings, sales or running auctions
+    let existing = COLLECTIONINFO.may_load(deps.storage,&nft_address)?;
+    let (auction_id, num_offerings) = existing.as_ref()
+        .map(|collection_info| (collection_info.auction_id, collection_info.num_offerings))
+        .unwrap_or_default();
+    let (offering_id, sale_id) = match &existing {
+        Some(collection_info) => (collection_info.offering_id.max(offering_id), collection_info.sale_id.max(sale_id)),
+        None => (offering_id, sale_id),
+    };
+
+    MEMBERS.save(deps.storage,&nft_address, &members)?;
+    COLLECTIONINFO.save(deps.storage,&nft_address,&CollectionInfo{
+        nft_address:nft_address.clone(),
+        offering_id,
+        sale_id,
+        royalty_portion:royalty_potion,
+        auction_id,
+        num_offerings,
+        fee,
+        royalty_mode:royalty_mode.unwrap_or_default()
+    })?;
+    Ok(Response::new()
+        .add_attribute("action", "add_collection")
+        .add_attribute("collection", nft_address)
+        .add_attribute("royalty_portion", royalty_potion.to_string()))
 }
 
-fn parse_keys(
-    _deps:Deps,
-    item: StdResult<(String,String)>,
-) -> StdResult<String> {
-    item.and_then(|(_address,token_id)| {
-        Ok(token_id)
-    })
+
+fn execute_update_collection(
+    deps: DepsMut,
+    _env:Env,
+    info: MessageInfo,
+    royalty_potion: Decimal,
+    members: Vec<UserInfo>,
+    nft_address:String,
+    fee:Option<Decimal>,
+    clear_fee:bool,
+    royalty_mode:Option<RoyaltyMode>
+)->Result<Response,ContractError>{
+
+    assert_role(deps.as_ref(), &info.sender, Role::Curator)?;
+
+    deps.api.addr_validate(&nft_address)?;
+
+    let collection_info = COLLECTIONINFO.may_load(deps.storage,&nft_address)?;
+    if collection_info == None{
+        return Err(ContractError::WrongCollection {  })
+    }
+    let collection_info = collection_info.unwrap();
+
+    let mut sum_portion = Decimal::zero();
+
+    for item in members.clone() {
+        sum_portion = sum_portion + item.portion;
+        deps.api.addr_validate(&item.address)?;
+    }
+
+    if sum_portion != Decimal::one(){
+        return Err(ContractError::WrongPortionError { })
+    }
+
+    if let Some(fee) = fee {
+        validate_fee(fee)?;
+    }
+
+    MEMBERS.save(deps.storage,&nft_address, &members)?;
+    COLLECTIONINFO.save(deps.storage,&nft_address,&CollectionInfo{
+        nft_address:nft_address.clone(),
+        offering_id:collection_info.offering_id,
+        royalty_portion:royalty_potion,
+        sale_id:collection_info.sale_id,
+        auction_id:collection_info.auction_id,
+        num_offerings:collection_info.num_offerings,
+        fee:if clear_fee { fee } else { fee.or(collection_info.fee) },
+        royalty_mode:royalty_mode.unwrap_or(collection_info.royalty_mode)
+    })?;
+    Ok(Response::new()
+        .add_attribute("action", "update_collection")
+        .add_attribute("collection", nft_address)
+        .add_attribute("royalty_portion", royalty_potion.to_string()))
+}
+
+
+fn execute_token_address(
+    deps: DepsMut,
+    _env:Env,
+    info: MessageInfo,
+    symbol:String,
+    address: String,
+) -> Result<Response, ContractError> {
+    assert_role(deps.as_ref(), &info.sender, Role::Curator)?;
+    deps.api.addr_validate(&address)?;
+
+    TOKENADDRESS.save(deps.storage,&address,&symbol)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "add_token_address")
+        .add_attribute("symbol", symbol)
+        .add_attribute("token", address))
+}
+
+
+fn execute_propose_nft_transfer(
+    deps: DepsMut,
+    env:Env,
+    info: MessageInfo,
+    nft_address: String,
+    token_ids: Vec<String>,
+    recipient: String
+) -> Result<Response, ContractError> {
+    assert_owner(deps.as_ref(), &info.sender)?;
+    deps.api.addr_validate(&nft_address)?;
+    let recipient = deps.api.addr_validate(&recipient)?.to_string();
+    for token_id in &token_ids {
+        if token_in_auction(deps.storage, &nft_address, token_id)? {
+            return Err(ContractError::NftInUse { token_id: token_id.clone() });
+        }
+    }
+
+    let id = NFT_TRANSFER_COUNT.may_load(deps.storage)?.unwrap_or_default() + 1;
+    NFT_TRANSFER_COUNT.save(deps.storage, &id)?;
+    let transfer = NftTransfer {
+        id,
+        nft_address,
+        token_ids,
+        recipient,
+        executable_at: env.block.time.seconds() + NFT_TRANSFER_DELAY,
+        consents: vec![]
+    };
+    nft_transfers().save(deps.storage, id, &transfer)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "propose_nft_transfer")
+        .add_attribute("transfer_id", id.to_string())
+        .add_attribute("collection", transfer.nft_address)
+        .add_attribute("token_ids", transfer.token_ids.join(","))
+        .add_attribute("recipient", transfer.recipient)
+        .add_attribute("executable_at", transfer.executable_at.to_string()))
+}
+
+fn execute_consent_nft_transfer(
+    deps: DepsMut,
+    _env:Env,
+    info: MessageInfo,
+    transfer_id: u64
+) -> Result<Response, ContractError> {
+    let mut transfer = nft_transfers().load(deps.storage, transfer_id)?;
+
+    let mut consented = vec![];
+    for token_id in &transfer.token_ids {
+        if let Some((_, offering)) = token_offering(deps.storage, &transfer.nft_address, token_id)? {
+            if info.sender == offering.seller {
+                consented.push(token_id.clone());
+                transfer.consents.push(TransferConsent { token_id: token_id.clone(), seller: offering.seller });
+            }
+        }
+    }
+    if consented.is_empty() {
+        return Err(ContractError::Unauthorized {});
+    }
+    nft_transfers().save(deps.storage, transfer_id, &transfer)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "consent_nft_transfer")
+        .add_attribute("transfer_id", transfer_id.to_string())
+        .add_attribute("seller", info.sender)
+        .add_attribute("token_ids", consented.join(",")))
+}
+
+/// Moves the nfts of a proposal once its timelock passed. Tokens listed since need the
+/// consent of their seller and tokens in auction can't be moved at all.
+fn execute_nft_transfer(
+    deps: DepsMut,
+    env:Env,
+    info: MessageInfo,
+    transfer_id: u64
+) -> Result<Response, ContractError> {
+    assert_owner(deps.as_ref(), &info.sender)?;
+    let transfer = nft_transfers().load(deps.storage, transfer_id)?;
+    if env.block.time.seconds() < transfer.executable_at {
+        return Err(ContractError::Timelocked { executable_at: transfer.executable_at });
+    }
+
+    let mut messages: Vec<CosmosMsg> = vec![];
+    for token_id in &transfer.token_ids {
+        if token_in_auction(deps.storage, &transfer.nft_address, token_id)? {
+            return Err(ContractError::NftInUse { token_id: token_id.clone() });
+        }
+        if let Some((offering_id, offering)) = token_offering(deps.storage, &transfer.nft_address, token_id)? {
+            let consented = transfer.consents.iter()
+                .any(|consent| consent.token_id == *token_id && consent.seller == offering.seller);
+            if !consented {
+                return Err(ContractError::NftInUse { token_id: token_id.clone() });
+            }
+            remove_offering(deps.storage, &transfer.nft_address, offering_id)?;
+        }
+        DEPOSITS.remove(deps.storage, (&transfer.nft_address, token_id));
+        messages.push(CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: transfer.nft_address.clone(),
+            funds: vec![],
+            msg: to_binary(&Cw721ExecuteMsg::TransferNft {
+                recipient: transfer.recipient.clone(),
+                token_id: token_id.clone(),
+            })?,
+        }));
+    }
+    nft_transfers().remove(deps.storage, transfer_id)?;
+
+    Ok(Response::new()
+        .add_messages(messages)
+        .add_attribute("action", "execute_nft_transfer")
+        .add_attribute("transfer_id", transfer_id.to_string())
+        .add_attribute("collection", transfer.nft_address)
+        .add_attribute("token_ids", transfer.token_ids.join(","))
+        .add_attribute("recipient", transfer.recipient))
+}
+
+fn execute_cancel_nft_transfer(
+    deps: DepsMut,
+    _env:Env,
+    info: MessageInfo,
+    transfer_id: u64
+) -> Result<Response, ContractError> {
+    assert_owner(deps.as_ref(), &info.sender)?;
+    let transfer = nft_transfers().load(deps.storage, transfer_id)?;
+    nft_transfers().remove(deps.storage, transfer_id)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "cancel_nft_transfer")
+        .add_attribute("transfer_id", transfer_id.to_string())
+        .add_attribute("collection", transfer.nft_address))
+}
+
+/// Sends an escrowed nft back to its depositor once nothing references it anymore, e.g. after
+/// its offering was overwritten. Pending transfer proposals of the nft are cancelled, the
+/// depositor's claim comes first. Stays available while trading is paused.
+fn execute_claim_orphaned_nft(
+    deps: DepsMut,
+    _env:Env,
+    info: MessageInfo,
+    nft_address: String,
+    token_id: String
+) -> Result<Response, ContractError> {
+    let depositor = DEPOSITS.may_load(deps.storage, (&nft_address, &token_id))?;
+    if depositor.as_deref() != Some(info.sender.as_str()) {
+        return Err(ContractError::Unauthorized {});
+    }
+    if token_offering(deps.storage, &nft_address, &token_id)?.is_some()
+        || token_in_auction(deps.storage, &nft_address, &token_id)? {
+        return Err(ContractError::NftInUse { token_id });
+    }
+    DEPOSITS.remove(deps.storage, (&nft_address, &token_id));
+    let cancelled = token_transfers(deps.storage, &nft_address, &token_id)?;
+    for transfer_id in &cancelled {
+        nft_transfers().remove(deps.storage, *transfer_id)?;
+    }
+
+    let mut response = Response::new()
+        .add_message(CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: nft_address.clone(),
+            funds: vec![],
+            msg: to_binary(&Cw721ExecuteMsg::TransferNft {
+                recipient: info.sender.to_string(),
+                token_id: token_id.clone(),
+            })?,
+        }))
+        .add_attribute("action", "claim_orphaned_nft")
+        .add_attribute("collection", nft_address)
+        .add_attribute("token_id", token_id)
+        .add_attribute("depositor", info.sender);
+    if !cancelled.is_empty() {
+        let ids: Vec<String> = cancelled.iter().map(|id| id.to_string()).collect();
+        response = response.add_attribute("cancelled_transfers", ids.join(","));
+    }
+    Ok(response)
+}
+
+/// The offering id and offering listing `token_id`, if any
+fn token_offering(storage: &dyn Storage, nft_address: &str, token_id: &str) -> StdResult<Option<(u64, Offering)>> {
+    offerings()
+        .idx
+        .token
+        .prefix((nft_address.to_string(), token_id.to_string()))
+        .range(storage, None, None, Order::Ascending)
+        .next()
+        .map(|item| item.map(|((_, id), offering)| (id, offering)))
+        .transpose()
+}
+
+fn token_in_auction(storage: &dyn Storage, nft_address: &str, token_id: &str) -> StdResult<bool> {
+    let auction = auctions()
+        .idx
+        .token
+        .prefix((nft_address.to_string(), token_id.to_string()))
+        .keys(storage, None, None, Order::Ascending)
+        .next()
+        .transpose()?;
+    Ok(auction.is_some())
+}
+
+/// Ids of the pending transfer proposals moving `token_id`
+fn token_transfers(storage: &dyn Storage, nft_address: &str, token_id: &str) -> StdResult<Vec<u64>> {
+    nft_transfers().idx.token.transfer_ids(storage, nft_address, token_id)
+}
+
+
+fn execute_propose_owner(
+    deps: DepsMut,
+    env:Env,
+    info: MessageInfo,
+    address: String,
+    expires: Option<u64>
+) -> Result<Response, ContractError> {
+    assert_owner(deps.as_ref(), &info.sender)?;
+    if matches!(expires, Some(expires) if expires <= env.block.time.seconds()) {
+        return Err(ContractError::InvalidExpiration {});
+    }
+    let pending = PendingOwner {
+        owner: deps.api.addr_validate(&address)?.to_string(),
+        expires
+    };
+    PENDING_OWNER.save(deps.storage, &pending)?;
+
+    let mut response = Response::new()
+        .add_attribute("action", "propose_owner")
+        .add_attribute("owner", info.sender)
+        .add_attribute("pending_owner", pending.owner);
+    if let Some(expires) = expires {
+        response = response.add_attribute("expires", expires.to_string());
+    }
+    Ok(response)
+}
+
+/// Completes a proposed ownership transfer, called by the proposed owner.
+fn execute_accept_ownership(
+    deps: DepsMut,
+    env:Env,
+    info: MessageInfo
+) -> Result<Response, ContractError> {
+    let pending = PENDING_OWNER.may_load(deps.storage)?.ok_or(ContractError::NoPendingOwner {})?;
+
+    if info.sender != pending.owner {
+        return Err(ContractError::Unauthorized {});
+    }
+    if matches!(pending.expires, Some(expires) if expires <= env.block.time.seconds()) {
+        return Err(ContractError::ProposalExpired {});
+    }
+    let mut state = CONFIG.load(deps.storage)?;
+    let previous_owner = state.owner;
+    state.owner = pending.owner;
+    CONFIG.save(deps.storage, &state)?;
+    PENDING_OWNER.remove(deps.storage);
+
+    Ok(Response::new()
+        .add_attribute("action", "accept_ownership")
+        .add_attribute("previous_owner", previous_owner)
+        .add_attribute("owner", state.owner))
+}
+
+fn execute_cancel_proposal(
+    deps: DepsMut,
+    _env:Env,
+    info: MessageInfo
+) -> Result<Response, ContractError> {
+    assert_owner(deps.as_ref(), &info.sender)?;
+    let pending = PENDING_OWNER.may_load(deps.storage)?.ok_or(ContractError::NoPendingOwner {})?;
+    PENDING_OWNER.remove(deps.storage);
+
+    Ok(Response::new()
+        .add_attribute("action", "cancel_proposal")
+        .add_attribute("pending_owner", pending.owner))
+}
+
+fn execute_renounce_ownership(
+    deps: DepsMut,
+    _env:Env,
+    info: MessageInfo
+) -> Result<Response, ContractError> {
+    assert_owner(deps.as_ref(), &info.sender)?;
+    // No sender matches an empty owner
+    let mut state = CONFIG.load(deps.storage)?;
+    let previous_owner = state.owner;
+    state.owner = String::new();
+    CONFIG.save(deps.storage, &state)?;
+    PENDING_OWNER.remove(deps.storage);
+    // Nobody could revoke roles granted before
+    for role in Role::ALL {
+        let members: Vec<String> = ROLES
+            .prefix(role.as_str())
+            .keys(deps.storage, None, None, Order::Ascending)
+            .collect::<StdResult<_>>()?;
+        for member in members {
+            ROLES.remove(deps.storage, (role.as_str(), &member));
+        }
+    }
+
+    Ok(Response::new()
+        .add_attribute("action", "renounce_ownership")
+        .add_attribute("previous_owner", previous_owner))
+}
+
+fn execute_set_paused(
+    deps: DepsMut,
+    _env:Env,
+    info: MessageInfo,
+    nft_address: Option<String>,
+    paused: bool
+) -> Result<Response, ContractError> {
+    assert_role(deps.as_ref(), &info.sender, Role::Operator)?;
+
+    let action = if paused { "pause" } else { "unpause" };
+    let mut response = Response::new().add_attribute("action", action);
+    match nft_address {
+        Some(nft_address) => {
+            if paused {
+                PAUSED_COLLECTIONS.save(deps.storage, &nft_address, &true)?;
+            } else {
+                PAUSED_COLLECTIONS.remove(deps.storage, &nft_address);
+            }
+            response = response.add_attribute("collection", nft_address);
+        }
+        None => PAUSED.save(deps.storage, &paused)?,
+    }
+    Ok(response)
+}
+
+fn execute_grant_role(
+    deps: DepsMut,
+    _env:Env,
+    info: MessageInfo,
+    role: Role,
+    address: String
+) -> Result<Response, ContractError> {
+    assert_owner(deps.as_ref(), &info.sender)?;
+    let address = deps.api.addr_validate(&address)?;
+    ROLES.save(deps.storage, (role.as_str(), address.as_str()), &true)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "grant_role")
+        .add_attribute("role", role.as_str())
+        .add_attribute("address", address))
 }
 
+fn execute_revoke_role(
+    deps: DepsMut,
+    _env:Env,
+    info: MessageInfo,
+    role: Role,
+    address: String
+) -> Result<Response, ContractError> {
+    assert_owner(deps.as_ref(), &info.sender)?;
+    ROLES.remove(deps.storage, (role.as_str(), &address));
+
+    Ok(Response::new()
+        .add_attribute("action", "revoke_role")
+        .add_attribute("role", role.as_str())
+        .add_attribute("address", address))
+}
+
+fn execute_update_config(
+    deps: DepsMut,
+    _env:Env,
+    info: MessageInfo,
+    fee: Option<Decimal>,
+    fee_collector: Option<String>,
+    fee_tiers: Option<Vec<FeeTier>>
+) -> Result<Response, ContractError> {
+    assert_role(deps.as_ref(), &info.sender, Role::Treasury)?;
+    let mut state = CONFIG.load(deps.storage)?;
+
+    if let Some(fee) = fee {
+        validate_fee(fee)?;
+        state.fee = fee;
+    }
+    if let Some(fee_collector) = fee_collector {
+        state.fee_collector = deps.api.addr_validate(&fee_collector)?.to_string();
+    }
+    if let Some(fee_tiers) = fee_tiers {
+        if fee_tiers.iter().any(|tier| tier.discount > Decimal::one()) {
+            return Err(ContractError::InvalidFee {});
+        }
+        state.fee_tiers = fee_tiers;
+    }
+    CONFIG.save(deps.storage,&state)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "update_config")
+        .add_attribute("fee", state.fee.to_string())
+        .add_attribute("fee_collector", state.fee_collector))
+}
+
+fn execute_set_tvl(
+    deps: DepsMut,
+    _env:Env,
+    info: MessageInfo,
+    address: String,
+    tvls: Vec<TvlInfo>,
+) -> Result<Response, ContractError> {
+    assert_role(deps.as_ref(), &info.sender, Role::Migrator)?;
+   
+    let count = tvls.len();
+    for tvl in tvls{
+        let denom = tvl_denom(deps.storage, tvl.denom)?;
+        TVL.save(deps.storage, (&address,&denom), &tvl.amount)?;
+    }
+
+    Ok(Response::new()
+        .add_attribute("action", "set_tvl")
+        .add_attribute("collection", address)
+        .add_attribute("count", count.to_string()))
+}
+
+fn execute_set_offerings(
+    deps: DepsMut,
+    _env:Env,
+    info:MessageInfo,
+    address: String,
+    new_offerings:Vec<QueryOfferingsResult>
+) -> Result<Response, ContractError> {
+    assert_role(deps.as_ref(), &info.sender, Role::Migrator)?;
+    
+    let mut collection_info = COLLECTIONINFO.may_load(deps.storage, &address)?
+        .ok_or(ContractError::WrongNFTContractError {})?;
+
+    let count = new_offerings.len();
+    for offering in new_offerings{
+        // Live offerings and their sellers are never overwritten
+        let id = offering_key(&offering.id)?;
+        if offerings().may_load(deps.storage, (&address,id))?.is_some()
+            || token_offering(deps.storage, &address, &offering.token_id)?.is_some() {
+            return Err(ContractError::OfferingExists {});
+        }
+        // Escrowed tokens only change hands through the timelocked transfer proposals
+        if token_in_auction(deps.storage, &address, &offering.token_id)?
+            || !token_transfers(deps.storage, &address, &offering.token_id)?.is_empty() {
+            return Err(ContractError::NftInUse { token_id: offering.token_id });
+        }
+        // Only the depositor can be made seller, tokens without a record go through the transfer proposals
+        let depositor = DEPOSITS.may_load(deps.storage, (&address, &offering.token_id))?;
+        if depositor.as_ref() != Some(&offering.seller) {
+            return Err(ContractError::DepositorMismatch { token_id: offering.token_id });
+        }
+        collection_info.num_offerings += 1;
+        update_global_stats(deps.storage, |stats| stats.active_listings += 1)?;
+        collection_info.offering_id = collection_info.offering_id.max(id);
+        let crr_offering = Offering{
+            nft_address:address.clone(),
+            token_id:offering.token_id,
+            seller:offering.seller,
+            list_price:offering.list_price,
+            dutch_auction:offering.dutch_auction,
+            expires_at:offering.expires_at
+        };
+        offerings().save(deps.storage, (&address,id), &crr_offering)?;
+    }
+
+    COLLECTIONINFO.save(deps.storage, &address, &collection_info)?;
+   
+    Ok(Response::new()
+        .add_attribute("action", "set_offerings")
+        .add_attribute("collection", address)
+        .add_attribute("count", count.to_string()))
+}
+
+
+fn execute_history(
+    deps: DepsMut,
+    _env:Env,
+    info:MessageInfo,
+    address: String,
+    histories:Vec<SaleInfo>
+) -> Result<Response, ContractError> {
+    assert_role(deps.as_ref(), &info.sender, Role::Migrator)?;
+
+    // Imported sales continue after the collection's last sale id
+    let mut collection_info = COLLECTIONINFO.load(deps.storage, &address)?;
+    let count = histories.len();
+    for history in histories{
+        if history.nft_address != address {
+            return Err(ContractError::WrongCollection {});
+        }
+        collection_info.sale_id += 1;
+        sale_history().save(deps.storage, (&address,&collection_info.sale_id.to_string()), &history)?;
+    }
+    COLLECTIONINFO.save(deps.storage, &address, &collection_info)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "set_sale_history")
+        .add_attribute("collection", address)
+        .add_attribute("count", count.to_string()))
+}
+
+
+#[entry_point]
+pub fn migrate(deps: DepsMut, _env: Env, _msg: MigrateMsg) -> Result<Response, ContractError> {
+    let previous = get_contract_version(deps.storage)?;
+    if previous.contract != CONTRACT_NAME {
+        return Err(ContractError::WrongContract { contract: previous.contract });
+    }
+    let applied = migrate_storage(deps.storage, &previous.version, CONTRACT_VERSION)?;
+    set_contract_version(deps.storage, CONTRACT_NAME, CONTRACT_VERSION)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "migrate")
+        .add_attribute("from_version", previous.version)
+        .add_attribute("to_version", CONTRACT_VERSION)
+        .add_attribute("migrations", applied.join(",")))
+}
+
+#[entry_point]
+pub fn query(deps: Deps, env: Env, msg: QueryMsg) -> StdResult<Binary> {
+    match msg {
+        QueryMsg::GetStateInfo {} => to_binary(&query_state_info(deps)?),
+        QueryMsg::GetPendingOwner {} => to_binary(&query_pending_owner(deps)?),
+        QueryMsg::GetPauseStatus {} => to_binary(&query_pause_status(deps)?),
+        QueryMsg::GetNftTransfer { transfer_id } => to_binary(&query_nft_transfer(deps,transfer_id)?),
+        QueryMsg::ListNftTransfers { start_after, limit } => to_binary(&query_list_nft_transfers(deps,start_after,limit)?),
+        QueryMsg::ReconcileEscrow { nft_address, start_after, limit } => to_binary(&query_reconcile_escrow(deps,env.clone(),nft_address,start_after,limit)?),
+        QueryMsg::DanglingOfferings { nft_address, start_after, limit } => to_binary(&query_dangling_offerings(deps,env.clone(),nft_address,start_after,limit)?),
+        QueryMsg::GetRoles { address } => to_binary(&query_roles(deps,address)?),
+        QueryMsg::GetRoleMembers { role } => to_binary(&query_role_members(deps,role)?),
+        QueryMsg::GetFeeInfo {} => to_binary(&query_fee_info(deps)?),
+        QueryMsg::GetEffectiveFee { address, denom } => to_binary(&query_effective_fee(deps, address, denom)?),
+        QueryMsg::GetMembers {address} => to_binary(&query_get_members(deps,address)?),
+        QueryMsg::GetTradingInfo { address} => to_binary(&query_get_trading(deps,address)?),
+        QueryMsg::GetVolumes { address } => to_binary(&query_volumes(deps,address)?),
+        QueryMsg::GetGlobalStats {} => to_binary(&query_global_stats(deps)?),
+        QueryMsg::GetSaleHistory {address, id } => to_binary(&query_get_history(deps,address,id)?),
+        QueryMsg::ListSales { nft_address, start_after, limit, descending } => to_binary(&query_list_sales(deps,nft_address,start_after,limit,descending.unwrap_or(false))?),
+        QueryMsg::ListAllSales { start_after, limit, descending } => to_binary(&query_list_all_sales(deps,start_after,limit,descending.unwrap_or(false))?),
+        QueryMsg::SalesByBuyer { buyer, start_after, limit, descending } => to_binary(&query_sales_by_buyer(deps,buyer,start_after,limit,descending.unwrap_or(false))?),
+        QueryMsg::SalesBySeller { seller, start_after, limit, descending } => to_binary(&query_sales_by_seller(deps,seller,start_after,limit,descending.unwrap_or(false))?),
+        QueryMsg::SalesByToken { nft_address, token_id, start_after, limit, descending } => to_binary(&query_sales_by_token(deps,nft_address,token_id,start_after,limit,descending.unwrap_or(false))?),
+        QueryMsg::GetCollectionInfo { address } =>to_binary(&query_collection_info(deps,address)?),
+        QueryMsg::GetOfferingId {address }=> to_binary(&query_get_ids(deps,address)?),
+        QueryMsg::GetOfferingPage { id,address,exclude_expired }  => to_binary(&query_get_offering(deps,env.clone(),id,address,exclude_expired.unwrap_or(false))?),
+        QueryMsg::ListOfferings { nft_address, start_after, limit } => to_binary(&query_list_offerings(deps,nft_address,start_after,limit)?),
+        QueryMsg::ListAllOfferings { start_after, limit } => to_binary(&query_list_all_offerings(deps,start_after,limit)?),
+        QueryMsg::OfferingsBySeller { seller, start_after, limit } => to_binary(&query_offerings_by_seller(deps,seller,start_after,limit)?),
+        QueryMsg::OfferingByToken { nft_address, token_id } => to_binary(&query_offering_by_token(deps,nft_address,token_id)?),
+        QueryMsg::OfferingsByPrice { nft_address, asset, start_after, limit, descending } => to_binary(&query_offerings_by_price(deps,nft_address,asset,start_after,limit,descending.unwrap_or(false))?),
+        QueryMsg::DutchAuctions { nft_address, asset, start_after, limit, descending } => to_binary(&query_dutch_auctions(deps,nft_address,asset,start_after,limit,descending.unwrap_or(false))?),
+        QueryMsg::FloorPrice { nft_address, asset } => to_binary(&query_floor_price(deps,env,nft_address,asset)?),
+        QueryMsg::GetTvl { address, symbol }=> to_binary(&query_get_tvl(deps,address,symbol)?),
+        QueryMsg::GetTvlAll { address, symbols }=> to_binary(&query_all_tvl(deps,address,symbols)?),
+        QueryMsg::GetAuctionIds { address } => to_binary(&query_get_auction_ids(deps,address)?),
+        QueryMsg::GetAuctionPage { id, address } => to_binary(&query_get_auctions(deps,id,address)?),
+        QueryMsg::GetCurrentPrice { address, offering_id } => to_binary(&query_current_price(deps,env,address,offering_id)?),
+        QueryMsg::GetTokenOffers { address, token_id } => to_binary(&query_token_offers(deps,address,token_id)?),
+        QueryMsg::GetBidderOffers { bidder } => to_binary(&query_bidder_offers(deps,bidder)?),
+        QueryMsg::GetCollectionOffers { address } => to_binary(&query_collection_offers(deps,address)?)
+    }
+}
+
+pub fn query_state_info(deps:Deps) -> StdResult<State>{
+    let state =  CONFIG.load(deps.storage)?;
+    Ok(state)
+}
+
+pub fn query_pending_owner(deps:Deps) -> StdResult<Option<PendingOwner>>{
+    PENDING_OWNER.may_load(deps.storage)
+}
+
+pub fn query_pause_status(deps:Deps) -> StdResult<PauseStatusResponse>{
+    Ok(PauseStatusResponse {
+        global: PAUSED.may_load(deps.storage)?.unwrap_or(false),
+        collections: PAUSED_COLLECTIONS
+            .keys(deps.storage, None, None, Order::Ascending)
+            .collect::<StdResult<_>>()?
+    })
+}
+
+pub fn query_nft_transfer(deps:Deps,transfer_id:u64) -> StdResult<Option<NftTransfer>>{
+    nft_transfers().may_load(deps.storage, transfer_id)
+}
+
+pub fn query_list_nft_transfers(deps:Deps,start_after:Option<u64>,limit:Option<u32>) -> StdResult<Vec<NftTransfer>>{
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    nft_transfers()
+        .range(deps.storage, start_after.map(Bound::exclusive), None, Order::Ascending)
+        .take(limit)
+        .map(|item| item.map(|(_, transfer)| transfer))
+        .collect()
+}
+
+/// Pages through the tokens the contract owns according to the cw721 contract, reporting those
+/// no offering or auction references.
+pub fn query_reconcile_escrow(deps:Deps,env:Env,nft_address:String,start_after:Option<String>,limit:Option<u32>) -> StdResult<EscrowReconciliationResponse>{
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT);
+    let held: TokensResponse = deps.querier.query_wasm_smart(&nft_address, &Cw721QueryMsg::Tokens {
+        owner: env.contract.address.to_string(),
+        start_after,
+        limit: Some(limit),
+    })?;
+    let next_start_after = if held.tokens.len() < limit as usize { None } else { held.tokens.last().cloned() };
+
+    let mut orphaned = vec![];
+    for token_id in held.tokens {
+        if token_offering(deps.storage, &nft_address, &token_id)?.is_some()
+            || token_in_auction(deps.storage, &nft_address, &token_id)? {
+            continue;
+        }
+        orphaned.push(OrphanedNft {
+            depositor: DEPOSITS.may_load(deps.storage, (&nft_address, &token_id))?,
+            token_id
+        });
+    }
+    Ok(EscrowReconciliationResponse { orphaned, next_start_after })
+}
+
+/// Pages through the offerings of a collection by id, reporting those whose token the contract
+/// doesn't own. Tokens the cw721 contract doesn't know anymore count as not owned.
+pub fn query_dangling_offerings(deps:Deps,env:Env,nft_address:String,start_after:Option<String>,limit:Option<u32>) -> StdResult<DanglingOfferingsResponse>{
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start = start_after.as_deref().map(offering_key).transpose()?.map(Bound::exclusive);
+    let page: Vec<(u64, Offering)> = offerings()
+        .prefix(&nft_address)
+        .range(deps.storage, start, None, Order::Ascending)
+        .take(limit)
+        .collect::<StdResult<_>>()?;
+    let next_start_after = if page.len() < limit { None } else { page.last().map(|(id, _)| id.to_string()) };
+
+    let mut dangling = vec![];
+    for (id, offering) in page {
+        let owner = deps.querier.query_wasm_smart::<OwnerOfResponse>(&nft_address, &Cw721QueryMsg::OwnerOf {
+            token_id: offering.token_id.clone(),
+            include_expired: None,
+        });
+        if !matches!(owner, Ok(owner) if owner.owner == env.contract.address) {
+            dangling.push(offering_result(id, offering));
+        }
+    }
+    Ok(DanglingOfferingsResponse { offerings: dangling, next_start_after })
+}
+
+pub fn query_roles(deps:Deps,address:String) -> StdResult<Vec<Role>>{
+    Ok(Role::ALL
+        .iter()
+        .filter(|role| ROLES.has(deps.storage, (role.as_str(), &address)))
+        .copied()
+        .collect())
+}
+
+pub fn query_role_members(deps:Deps,role:Role) -> StdResult<Vec<String>>{
+    ROLES
+        .prefix(role.as_str())
+        .keys(deps.storage, None, None, Order::Ascending)
+        .collect()
+}
+
+pub fn query_fee_info(deps:Deps) -> StdResult<FeeInfo>{
+    let state =  CONFIG.load(deps.storage)?;
+    Ok(FeeInfo {
+        fee: state.fee,
+        fee_collector: state.fee_collector,
+        fee_tiers: state.fee_tiers
+    })
+}
+
+pub fn query_effective_fee(deps:Deps, address:String, denom:String) -> StdResult<Decimal>{
+    let state = CONFIG.load(deps.storage)?;
+    let collection_info = COLLECTIONINFO.load(deps.storage,&address)?;
+    effective_fee(deps.storage, &state, &collection_info, &denom)
+}
+
+pub fn query_collection_info(deps:Deps,address:String) -> StdResult<CollectionInfo>{
+    let collection_info =  COLLECTIONINFO.load(deps.storage,&address)?;
+    Ok(collection_info)
+}
+
+
+/// TVL is keyed by native denom or cw20 contract address, registered cw20 symbols are
+/// resolved to their address.
+fn tvl_denom(storage: &dyn Storage, symbol: String) -> StdResult<String> {
+    for item in TOKENADDRESS.range(storage, None, None, Order::Ascending) {
+        let (address, token_symbol) = item?;
+        if token_symbol == symbol {
+            return Ok(address);
+        }
+    }
+    Ok(symbol)
+}
+
+pub fn query_get_tvl(deps:Deps,address:String,symbol:String) -> StdResult<Uint128>{
+    let denom = tvl_denom(deps.storage, symbol)?;
+    let tvl = TVL.may_load(deps.storage, (&address,&denom))?;
+    if tvl == None{
+        Ok(Uint128::new(0))
+    }
+    else{
+        Ok(tvl.unwrap())
+    }
+}
+
+pub fn query_all_tvl(deps:Deps,address:String,symbols:Vec<String>) -> StdResult<Vec<TvlInfo>>{
+    let mut empty:Vec<TvlInfo> = vec![];
+    for symbol in symbols
+    {
+        let denom = tvl_denom(deps.storage, symbol.clone())?;
+        let tvl = TVL.may_load(deps.storage, (&address,&denom))?;
+        if tvl == None{
+            empty.push(TvlInfo { denom: symbol, amount: Uint128::new(0) })
+        }
+        else{
+              empty.push(TvlInfo { denom: symbol, amount: tvl.unwrap() })
+         
+        }
+    }
+    Ok(empty)
+}
+
+
+pub fn query_get_members(deps:Deps,address:String) -> StdResult<Vec<UserInfo>>{
+    let members = MEMBERS.load(deps.storage,&address)?;
+    Ok(members)
+}
+
+/// Volume in ujuno and in the cw20 registered as "hope", see `query_volumes` for every asset.
+pub fn query_get_trading(deps:Deps,address:String) -> StdResult<PriceInfo>{
+    let mut price_info = PriceInfo {
+        total_hope:Uint128::new(0),
+        total_juno:Uint128::new(0)
+    };
+    for volume in query_volumes(deps, address)? {
+        match &volume.info {
+            AssetInfo::NativeToken { denom } if denom == "ujuno" => price_info.total_juno += volume.amount,
+            AssetInfo::Token { contract_addr } if TOKENADDRESS.may_load(deps.storage, contract_addr)?.as_deref() == Some("hope") => {
+                price_info.total_hope += volume.amount
+            }
+            _ => {}
+        }
+    }
+    Ok(price_info)
+}
+
+pub fn query_global_stats(deps:Deps) -> StdResult<GlobalStatsResponse>{
+    let stats = GLOBAL_STATS.may_load(deps.storage)?.unwrap_or_default();
+    let volumes = GLOBAL_VOLUMES
+        .range(deps.storage, None, None, Order::Ascending)
+        .map(|item| item.map(|(_, volume)| volume))
+        .collect::<StdResult<_>>()?;
+    Ok(GlobalStatsResponse {
+        total_sales: stats.total_sales,
+        active_listings: stats.active_listings,
+        unique_buyers: stats.unique_buyers,
+        unique_sellers: stats.unique_sellers,
+        volumes
+    })
+}
+
+pub fn query_volumes(deps:Deps,address:String) -> StdResult<Vec<VolumeInfo>>{
+    VOLUMES
+        .prefix(&address)
+        .range(deps.storage, None, None, Order::Ascending)
+        .map(|item| item.map(|(_, volume)| volume))
+        .collect()
+}
+
+// pub fn query_get_offerings(deps:Deps) -> StdResult<OfferingsResponse>{
+//     let res: StdResult<Vec<QueryOfferingsResult>> = OFFERINGS
+//         .range(deps.storage, None, None, Order::Ascending)
+//         .map(|kv_item| parse_offering(deps, kv_item  ))
+//         .collect();
+//     Ok(OfferingsResponse {
+//         offerings: res?, // Placeholder
+//     })
+// }
+
+// fn parse_offering(
+//     deps:Deps,
+//     item: StdResult<((String,String),Offering)>,
+// ) -> StdResult<QueryOfferingsResult> {
+//     item.and_then(|((address,k), offering)| {
+//         Ok(QueryOfferingsResult {
+//             id: k,
+//             token_id: offering.token_id,
+//             list_price: offering.list_price,
+//             seller: deps.api.addr_validate(&offering.seller)?.to_string(),
+//         })
+//     })
+// }
+
+
+pub fn query_get_ids(deps:Deps,address: String) -> StdResult<Vec<String>>{
+    offerings()
+        .prefix(&address)
+        .keys(deps.storage, None, None, Order::Ascending)
+        .map(|id| id.map(|id| id.to_string()))
+        .collect()
+}
+
+
+pub fn query_get_offering(deps:Deps,env:Env,ids:Vec<String>,address: String,exclude_expired:bool) -> StdResult<Vec<QueryOfferingsResult>>{
+    let mut offering_group:Vec<QueryOfferingsResult> = vec![];
+    // Ids that aren't numbers can't name an offering
+    for id in ids.iter().filter_map(|id| offering_key(id).ok()){
+        let offering = offerings().may_load(deps.storage,(&address,id))?;
+        if offering!=None{
+            let offering = offering.unwrap();
+            if exclude_expired && offering.is_expired(env.block.time.seconds()) {
+                continue;
+            }
+            offering_group.push(offering_result(id, offering));
+        }
+    }
+    Ok(offering_group)
+}
+
+fn offering_result(id:u64, offering:Offering) -> QueryOfferingsResult {
+    QueryOfferingsResult{
+        id:id.to_string(),
+        token_id:offering.token_id,
+        list_price:offering.list_price,
+        seller:offering.seller,
+        dutch_auction:offering.dutch_auction,
+        expires_at:offering.expires_at
+    }
+}
+
+pub fn query_list_offerings(deps:Deps,nft_address:String,start_after:Option<String>,limit:Option<u32>) -> StdResult<OfferingsResponse>{
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start = start_after.as_deref().map(offering_key).transpose()?.map(Bound::exclusive);
+    let page = offerings()
+        .prefix(&nft_address)
+        .range(deps.storage, start, None, Order::Ascending)
+        .take(limit)
+        .map(|item| item.map(|(id, offering)| offering_result(id, offering)))
+        .collect::<StdResult<_>>()?;
+    Ok(OfferingsResponse { offerings: page })
+}
+
+pub fn query_list_all_offerings(deps:Deps,start_after:Option<(String,String)>,limit:Option<u32>) -> StdResult<AllOfferingsResponse>{
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start = match &start_after {
+        Some((nft_address, id)) => Some(Bound::exclusive((nft_address.as_str(), offering_key(id)?))),
+        None => None,
+    };
+    let page = offerings()
+        .range(deps.storage, start, None, Order::Ascending)
+        .take(limit)
+        .map(|item| item.map(|((nft_address, id), offering)| CollectionOffering {
+            nft_address,
+            offering: offering_result(id, offering)
+        }))
+        .collect::<StdResult<_>>()?;
+    Ok(AllOfferingsResponse { offerings: page })
+}
+
+pub fn query_offerings_by_seller(deps:Deps,seller:String,start_after:Option<(String,String)>,limit:Option<u32>) -> StdResult<AllOfferingsResponse>{
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start = match start_after {
+        Some((nft_address, id)) => Some(Bound::exclusive((nft_address, offering_key(&id)?))),
+        None => None,
+    };
+    let page = offerings()
+        .idx
+        .seller
+        .prefix(seller)
+        .range(deps.storage, start, None, Order::Ascending)
+        .take(limit)
+        .map(|item| item.map(|((nft_address, id), offering)| CollectionOffering {
+            nft_address,
+            offering: offering_result(id, offering)
+        }))
+        .collect::<StdResult<_>>()?;
+    Ok(AllOfferingsResponse { offerings: page })
+}
+
+pub fn query_offering_by_token(deps:Deps,nft_address:String,token_id:String) -> StdResult<Option<QueryOfferingsResult>>{
+    Ok(token_offering(deps.storage, &nft_address, &token_id)?.map(|(id, offering)| offering_result(id, offering)))
+}
+
+pub fn query_offerings_by_price(
+    deps:Deps,
+    nft_address:String,
+    asset:AssetInfo,
+    start_after:Option<(Uint128,String)>,
+    limit:Option<u32>,
+    descending:bool
+) -> StdResult<OfferingsResponse>{
+    offerings_by_pricing(deps, nft_address, asset, FIXED_PRICE, start_after, limit, descending)
+}
+
+pub fn query_dutch_auctions(
+    deps:Deps,
+    nft_address:String,
+    asset:AssetInfo,
+    start_after:Option<(Uint128,String)>,
+    limit:Option<u32>,
+    descending:bool
+) -> StdResult<OfferingsResponse>{
+    offerings_by_pricing(deps, nft_address, asset, DUTCH_AUCTION, start_after, limit, descending)
+}
+
+/// Offerings with `pricing` sorted by `Offering::min_price`
+fn offerings_by_pricing(
+    deps:Deps,
+    nft_address:String,
+    asset:AssetInfo,
+    pricing:&str,
+    start_after:Option<(Uint128,String)>,
+    limit:Option<u32>,
+    descending:bool
+) -> StdResult<OfferingsResponse>{
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let bound = match start_after {
+        Some((price, id)) => Some(Bound::exclusive((price_key(price), (nft_address.clone(), offering_key(&id)?)))),
+        None => None,
+    };
+    let (start, end, order) = page_bounds(bound, descending);
+    let page = offerings()
+        .idx
+        .price
+        .sub_prefix(((nft_address.clone(), asset.to_string()), pricing.to_string()))
+        .range(deps.storage, start, end, order)
+        .take(limit)
+        .map(|item| item.map(|((_, id), offering)| offering_result(id, offering)))
+        .collect::<StdResult<_>>()?;
+    Ok(OfferingsResponse { offerings: page })
+}
+
+pub fn query_floor_price(deps:Deps,env:Env,nft_address:String,asset:AssetInfo) -> StdResult<Option<FloorPriceResponse>>{
+    let time = env.block.time.seconds();
+    let mut floor: Option<(Uint128, u64, Offering)> = None;
+    for pricing in [FIXED_PRICE, DUTCH_AUCTION] {
+        let candidates = offerings()
+            .idx
+            .price
+            .sub_prefix(((nft_address.clone(), asset.to_string()), pricing.to_string()))
+            .range(deps.storage, None, None, Order::Ascending);
+        for item in candidates {
+            let ((_, id), offering) = item?;
+            // Offerings are sorted by the lowest price they can reach, nothing after can be cheaper
+            if matches!(&floor, Some((price, _, _)) if offering.min_price() >= *price) {
+                break;
+            }
+            if offering.is_expired(time) {
+                continue;
+            }
+            let price = offering.current_price(time);
+            if !matches!(&floor, Some((floor_price, _, _)) if price >= *floor_price) {
+                floor = Some((price, id, offering));
+            }
+        }
+    }
+    Ok(floor.map(|(price, id, offering)| FloorPriceResponse {
+        price: Asset { info: offering.list_price.info.clone(), amount: price },
+        offering: offering_result(id, offering)
+    }))
+}
+
+/// Range arguments continuing after `bound` in the requested direction
+fn page_bounds<'a, K: PrimaryKey<'a>>(bound: Option<Bound<'a, K>>, descending: bool) -> (Option<Bound<'a, K>>, Option<Bound<'a, K>>, Order) {
+    if descending {
+        (None, bound, Order::Descending)
+    } else {
+        (bound, None, Order::Ascending)
+    }
+}
+
+/// Bound after the sale (address, id) in the time sorted sale indexes
+fn sale_cursor<'a>(storage: &dyn Storage, address: String, id: String) -> StdResult<Bound<'a, (u64, (String, String))>> {
+    let sale = sale_history().load(storage, (&address, &id))?;
+    Ok(Bound::exclusive((sale.time, (address, id))))
+}
+
+fn sales_page(sales: impl Iterator<Item = StdResult<((String, String), SaleInfo)>>, limit: Option<u32>) -> StdResult<SalesResponse> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let sales = sales
+        .take(limit)
+        .map(|item| item.map(|((_, id), sale)| SaleResult { id, sale }))
+        .collect::<StdResult<_>>()?;
+    Ok(SalesResponse { sales })
+}
+
+pub fn query_list_sales(deps:Deps,nft_address:String,start_after:Option<String>,limit:Option<u32>,descending:bool) -> StdResult<SalesResponse>{
+    let bound = start_after.map(|id| sale_cursor(deps.storage, nft_address.clone(), id)).transpose()?;
+    let (start, end, order) = page_bounds(bound, descending);
+    let sales = sale_history()
+        .idx
+        .collection
+        .sub_prefix(nft_address)
+        .range(deps.storage, start, end, order);
+    sales_page(sales, limit)
+}
+
+pub fn query_list_all_sales(deps:Deps,start_after:Option<(String,String)>,limit:Option<u32>,descending:bool) -> StdResult<SalesResponse>{
+    let bound = start_after.map(|(address, id)| sale_cursor(deps.storage, address, id)).transpose()?;
+    let (start, end, order) = page_bounds(bound, descending);
+    let sales = sale_history().idx.time.range(deps.storage, start, end, order);
+    sales_page(sales, limit)
+}
+
+pub fn query_sales_by_buyer(deps:Deps,buyer:String,start_after:Option<(String,String)>,limit:Option<u32>,descending:bool) -> StdResult<SalesResponse>{
+    let bound = start_after.map(|(address, id)| sale_cursor(deps.storage, address, id)).transpose()?;
+    let (start, end, order) = page_bounds(bound, descending);
+    let sales = sale_history()
+        .idx
+        .buyer
+        .sub_prefix(buyer)
+        .range(deps.storage, start, end, order);
+    sales_page(sales, limit)
+}
+
+pub fn query_sales_by_seller(deps:Deps,seller:String,start_after:Option<(String,String)>,limit:Option<u32>,descending:bool) -> StdResult<SalesResponse>{
+    let bound = start_after.map(|(address, id)| sale_cursor(deps.storage, address, id)).transpose()?;
+    let (start, end, order) = page_bounds(bound, descending);
+    let sales = sale_history()
+        .idx
+        .seller
+        .sub_prefix(seller)
+        .range(deps.storage, start, end, order);
+    sales_page(sales, limit)
+}
+
+pub fn query_sales_by_token(deps:Deps,nft_address:String,token_id:String,start_after:Option<String>,limit:Option<u32>,descending:bool) -> StdResult<SalesResponse>{
+    let bound = start_after.map(|id| sale_cursor(deps.storage, nft_address.clone(), id)).transpose()?;
+    let (start, end, order) = page_bounds(bound, descending);
+    let sales = sale_history()
+        .idx
+        .token
+        .sub_prefix((nft_address, token_id))
+        .range(deps.storage, start, end, order);
+    sales_page(sales, limit)
+}
+
+pub fn query_get_history(deps:Deps,address:String, ids:Vec<String>) -> StdResult<Vec<SaleInfo>>{
+    let mut sales : Vec<SaleInfo> = vec![];
+    for id in ids{
+       let history =  sale_history().may_load(deps.storage, (&address,&id))?;
+       if history != None{
+        sales.push(history.unwrap());
+       }
+    }
+    Ok(sales)
+}
+
+pub fn query_current_price(deps:Deps,env:Env,address:String,offering_id:String) -> StdResult<Asset>{
+    let offering = offerings().load(deps.storage,(&address,offering_key(&offering_id)?))?;
+    Ok(Asset {
+        info: offering.list_price.info.clone(),
+        amount: offering.current_price(env.block.time.seconds())
+    })
+}
+
+pub fn query_token_offers(deps:Deps,address:String,token_id:String) -> StdResult<Vec<TokenOffer>>{
+    token_offers()
+        .prefix((&address, &token_id))
+        .range(deps.storage, None, None, Order::Ascending)
+        .map(|item| item.map(|(_, offer)| offer))
+        .collect()
+}
+
+pub fn query_bidder_offers(deps:Deps,bidder:String) -> StdResult<Vec<TokenOffer>>{
+    token_offers()
+        .idx
+        .bidder
+        .prefix(bidder)
+        .range(deps.storage, None, None, Order::Ascending)
+        .map(|item| item.map(|(_, offer)| offer))
+        .collect()
+}
+
+pub fn query_collection_offers(deps:Deps,address:String) -> StdResult<Vec<CollectionOffer>>{
+    COLLECTION_OFFERS
+        .prefix(&address)
+        .range(deps.storage, None, None, Order::Ascending)
+        .map(|item| item.map(|(_, offer)| offer))
+        .collect()
+}
+
+pub fn query_get_auction_ids(deps:Deps,address: String) -> StdResult<Vec<String>>{
+    auctions()
+        .prefix(&address)
+        .keys(deps.storage, None, None, Order::Ascending)
+        .collect()
+}
+
+pub fn query_get_auctions(deps:Deps,ids:Vec<String>,address: String) -> StdResult<Vec<QueryAuctionsResult>>{
+    let mut results:Vec<QueryAuctionsResult> = vec![];
+    for id in ids{
+        if let Some(auction) = auctions().may_load(deps.storage,(&address,&id))? {
+            results.push(QueryAuctionsResult{
+                id,
+                token_id:auction.token_id,
+                seller:auction.seller,
+                reserve_price:auction.reserve_price,
+                min_increment:auction.min_increment,
+                end_time:auction.end_time,
+                highest_bid:auction.highest_bid
+            });
+        }
+    }
+    Ok(results)
+}
+
+#[cfg(test)]
+mod tests {
+  
+    use super::*;
+    use crate::msg::StartDutchAuction;
+    use cosmwasm_std::testing::{mock_dependencies, mock_env, mock_info};
+    use cosmwasm_std::{ CosmosMsg, Coin, BankMsg, coins, attr, WasmQuery, SystemResult, SystemError, ContractResult};
+    use cw20::Cw20ExecuteMsg;
+    use cw_storage_plus::Map;
+    use crate::migrations::{StoredOffering, StoredPrice, STORED_OFFERINGS};
+    use crate::state::PRICEINFO;
+
+    #[test]
+    fn testing() {
+        //Instantiate
+        let mut deps = mock_dependencies();
+        let instantiate_msg = InstantiateMsg {
+           owner:"creator".to_string(),
+           fee:None,
+           fee_collector:None
+        };
+        let info = mock_info("creator", &[]);
+        let res = instantiate(deps.as_mut(), mock_env(), info, instantiate_msg).unwrap();
+        assert_eq!(0, res.messages.len());
+        let state = query_state_info(deps.as_ref()).unwrap();
+        assert_eq!(state.owner,"creator".to_string());
+       
+
+        //Change Owner
+
+        let info = mock_info("creator", &[]);
+        let msg = ExecuteMsg::ProposeOwner { address:"owner".to_string(), expires:None };
+        execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+        let info = mock_info("owner", &[]);
+        execute(deps.as_mut(), mock_env(), info, ExecuteMsg::AcceptOwnership {}).unwrap();
+        let state = query_state_info(deps.as_ref()).unwrap();
+        assert_eq!(state.owner,"owner".to_string());
+
+         //Change Token Contract Address
+
+        let info = mock_info("owner", &[]);
+        let msg = ExecuteMsg::AddTokenAddress  { address:"token_address".to_string(),symbol:"hope".to_string()};
+        execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+        
+        let info = mock_info("owner", &[]);
+         let msg = ExecuteMsg::AddTokenAddress  { address:"raw_address".to_string(),symbol:"raw".to_string()};
+        execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+       
+        //Hope1 Collection Add
+       let info = mock_info("owner", &[]);
+       let msg = ExecuteMsg::AddCollection {
+            royalty_portion: Decimal::from_ratio(5 as u128, 100 as u128), 
+            members: vec![UserInfo{
+                address:"admin1".to_string(),
+                portion:Decimal::from_ratio(3 as u128, 10 as u128)
+                },UserInfo{
+                address:"admin2".to_string(),
+                portion:Decimal::from_ratio(7 as u128, 10 as u128)
+                }] ,
+            nft_address: "hope1_address".to_string() ,
+            offering_id:0,
+            sale_id:0,
+            fee:None,
+            royalty_mode:None
+        };
+        execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+       
+
+       // Sell nft
+        let cw721_msg = SellNft{
+            list_price:Asset {
+                info:AssetInfo::NativeToken{ denom:"ujuno".to_string() },
+                amount:Uint128::new(1000000)
+            },
+            expires_at:None
+        };
+
+        let info = mock_info("hope1_address", &[]);
+        let msg = ExecuteMsg::ReceiveNft(Cw721ReceiveMsg{
+            sender:"owner1".to_string(),
+            token_id:"Hope.1".to_string(),
+            msg:to_binary(&cw721_msg).unwrap()
+        });
+        execute(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
+
+
+        let collection_info = query_collection_info(deps.as_ref(),
+                "hope1_address".to_string()).unwrap();
+        assert_eq!(collection_info,CollectionInfo{
+            nft_address:"hope1_address".to_string(),
+            offering_id:1,
+            royalty_portion:Decimal::from_ratio(5 as u128, 100 as u128),
+            sale_id:0,
+            auction_id:0,
+            num_offerings:1,
+            fee:None,
+            royalty_mode:RoyaltyMode::Static
+            });
+
+      
+        let offerings = query_get_offering(deps.as_ref(),mock_env(),vec!["1".to_string(),"2".to_string()],"hope1_address".to_string(),false).unwrap();
+        assert_eq!(offerings,vec![QueryOfferingsResult{
+            id:"1".to_string(),
+            token_id:"Hope.1".to_string(),
+            list_price:Asset { info:AssetInfo::NativeToken{ denom:"ujuno".to_string() }, amount: Uint128::new(1000000) },
+            seller:"owner1".to_string(),
+            dutch_auction:None,
+            expires_at:None
+        }]);
+
+            //Buy nft
+
+      let info = mock_info("test_buyer1", &[Coin{
+        denom:"ujuno".to_string(),
+        amount:Uint128::new(1000000)
+      }]);
+      let msg = ExecuteMsg::BuyNft { offering_id: "1".to_string(), nft_address: "hope1_address".to_string(), token_id: Some("Hope.1".to_string()) };
+      let res = execute(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
+      assert_eq!(res.messages.len(),4);
+
+       let collection_info = query_collection_info(deps.as_ref(),"hope1_address".to_string()).unwrap();
+       assert_eq!(collection_info.offering_id,1);
+       assert_eq!(collection_info.num_offerings,0);
+
+      assert_eq!(res.messages[0].msg,CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: "hope1_address".to_string(),
+                funds: vec![],
+                msg: to_binary(&Cw721ExecuteMsg::TransferNft {
+                    recipient: "test_buyer1".to_string(),
+                    token_id:"Hope.1".to_string(),
+            }).unwrap(),
+        }));
+
+      assert_eq!(res.messages[1].msg,CosmosMsg::Bank(BankMsg::Send {
+                to_address: "owner1".to_string(),
+                amount:vec![Coin{
+                    denom:"ujuno".to_string(),
+                    amount:Uint128::new(950000)
+                }]
+        }));
+
+        assert_eq!(res.messages[2].msg,CosmosMsg::Bank(BankMsg::Send {
+                to_address: "admin1".to_string(),
+                amount:vec![Coin{
+                    denom:"ujuno".to_string(),
+                    amount:Uint128::new(15000)
+                }]
+        }));
+        assert_eq!(res.messages[3].msg,CosmosMsg::Bank(BankMsg::Send {
+                to_address: "admin2".to_string(),
+                amount:vec![Coin{
+                    denom:"ujuno".to_string(),
+                    amount:Uint128::new(35000)
+                }]
+        }));
+                                                                    
+        let ids =  query_get_ids(deps.as_ref(),"hope1_address".to_string()).unwrap();
+        let test_id:Vec<String> = vec![];
+        assert_eq!(ids,test_id);
+        
+
+         // Offering ids are never reused
+
+         //sell
+        let cw721_msg = SellNft{
+            list_price:Asset {
+                info:AssetInfo::NativeToken{ denom:"osmos".to_string() },
+                amount:Uint128::new(2000000)
+            },
+            expires_at:None
+        };
+
+        let info = mock_info("hope1_address", &[]);
+        let msg = ExecuteMsg::ReceiveNft(Cw721ReceiveMsg{
+            sender:"buyer1".to_string(),
+            token_id:"Hope.1".to_string(),
+            msg:to_binary(&cw721_msg).unwrap()
+        });
+         execute(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
+     
+         //sell
+         let cw721_msg = SellNft{
+            list_price:Asset {
+                info:AssetInfo::NativeToken{ denom:"ujuno".to_string() },
+                amount:Uint128::new(2000000)
+            },
+            expires_at:None
+        };
+
+          let info = mock_info("hope1_address", &[]);
+          let msg = ExecuteMsg::ReceiveNft(Cw721ReceiveMsg{
+            sender:"buyer2".to_string(),
+            token_id:"Hope.2".to_string(),
+            msg:to_binary(&cw721_msg).unwrap()
+            });
+         execute(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
+
+         //buy
+
+        let info = mock_info("test_buyer2", &[Coin{
+            denom:"osmos".to_string(),
+            amount:Uint128::new(2000000)
+        }]);
+        let msg = ExecuteMsg::BuyNft { offering_id: "2".to_string(), nft_address: "hope1_address".to_string(), token_id: Some("Hope.2".to_string()) };
+        let err = execute(deps.as_mut(), mock_env(), info.clone(), msg).unwrap_err();
+        assert!(matches!(err, ContractError::TokenIdMismatch {}));
+
+        let msg = ExecuteMsg::BuyNft { offering_id: "2".to_string(), nft_address: "hope1_address".to_string(), token_id: None };
+        execute(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
+
+        let id = query_get_ids(deps.as_ref(), "hope1_address".to_string()).unwrap();
+        let collection_info = query_collection_info(deps.as_ref(),"hope1_address".to_string()).unwrap();
+        assert_eq!(collection_info.offering_id,3);  
+        assert_eq!(collection_info.num_offerings,1);  
+        assert_eq!(id,vec!["3"]);
+
+         let cw721_msg = SellNft{
+            list_price:Asset {
+                info:AssetInfo::Token{ contract_addr:"token_address".to_string() },
+                amount:Uint128::new(2000000)
+            },
+            expires_at:None
+        };
+
+        let info = mock_info("hope1_address", &[]);
+        let msg = ExecuteMsg::ReceiveNft(Cw721ReceiveMsg{
+            sender:"buyer3".to_string(),
+            token_id:"Hope.3".to_string(),
+            msg:to_binary(&cw721_msg).unwrap()
+        });
+
+        execute(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
+
+        let ids = query_get_ids(deps.as_ref(),"hope1_address".to_string()).unwrap();
+        assert_eq!(ids,vec!["3".to_string(),"4".to_string()]);
+        let offerings = query_get_offering(deps.as_ref(),mock_env(),vec!["3".to_string(),"4".to_string()],"hope1_address".to_string(),false).unwrap();
+        assert_eq!(offerings,vec![QueryOfferingsResult{
+            id:"3".to_string(),
+            token_id:"Hope.2".to_string(),
+            list_price:Asset { info:AssetInfo::NativeToken{ denom:"ujuno".to_string() },  amount:Uint128::new(2000000) },
+            seller:"buyer2".to_string(),
+            dutch_auction:None,
+            expires_at:None
+        },QueryOfferingsResult{
+            id:"4".to_string(),
+            token_id:"Hope.3".to_string(),
+            list_price:Asset { info:AssetInfo::Token{ contract_addr:"token_address".to_string() },  amount:Uint128::new(2000000) },
+            seller:"buyer3".to_string(),
+            dutch_auction:None,
+            expires_at:None
+        }]);
+
+        let cw20_msg= BuyNft{
+            offering_id:"4".to_string(),
+            nft_address:"hope1_address".to_string(),
+            token_id:Some("Hope.3".to_string())
+        };
+
+        let info = mock_info("token_address", &[]);
+        let msg = ExecuteMsg::Receive(Cw20ReceiveMsg{
+            sender:"test_buyer3".to_string(),
+            amount:Uint128::new(2000000),
+            msg:to_binary(&cw20_msg).unwrap()
+        });
+        let res = execute(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();    
+        assert_eq!(res.messages[1].msg,CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: "token_address".to_string(),
+                funds: vec![],
+                msg: to_binary(&Cw20ExecuteMsg::Transfer { 
+                    recipient: "buyer3".to_string(), 
+                    amount:Uint128::new(1900000),
+                 }).unwrap()
+         }));
+
+         assert_eq!(res.messages[2].msg,CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: "token_address".to_string(),
+                funds: vec![],
+                msg: to_binary(&Cw20ExecuteMsg::Transfer { 
+                    recipient: "admin1".to_string(), 
+                    amount:Uint128::new(30000),
+                 }).unwrap()
+         }));
+
+          assert_eq!(res.messages[3].msg,CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: "token_address".to_string(),
+                funds: vec![],
+                msg: to_binary(&Cw20ExecuteMsg::Transfer { 
+                    recipient: "admin2".to_string(), 
+                    amount:Uint128::new(70000),
+                 }).unwrap()
+         }));
+
+
+        let offerings = query_get_offering(deps.as_ref(),mock_env(),vec!["3".to_string(),"4".to_string()],"hope1_address".to_string(),false).unwrap();
+        assert_eq!(offerings,vec![QueryOfferingsResult{
+            id:"3".to_string(),
+            token_id:"Hope.2".to_string(),
+            list_price:Asset { info:AssetInfo::NativeToken{ denom:"ujuno".to_string() },  amount:Uint128::new(2000000) },
+            seller:"buyer2".to_string(),
+            dutch_auction:None,
+            expires_at:None
+        }]);
+
+        let cw721_msg = SellNft{
+            list_price:Asset {
+                info:AssetInfo::Token{ contract_addr:"raw_address".to_string() },
+                amount:Uint128::new(2000000)
+            },
+            expires_at:None
+        };
+
+        let info = mock_info("hope1_address", &[]);
+        let msg = ExecuteMsg::ReceiveNft(Cw721ReceiveMsg{
+            sender:"owner1".to_string(),
+            token_id:"Hope.1".to_string(),
+            msg:to_binary(&cw721_msg).unwrap()
+        });
+        execute(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
+
+        let cw20_msg= BuyNft{
+            offering_id:"5".to_string(),
+            nft_address:"hope1_address".to_string(),
+            token_id:None
+        };
+
+        let info = mock_info("raw_address", &[]);
+        let msg = ExecuteMsg::Receive(Cw20ReceiveMsg{
+            sender:"test_buyer4".to_string(),
+            amount:Uint128::new(2000000),
+            msg:to_binary(&cw20_msg).unwrap()
+        });
+        execute(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
+
+        assert_eq!(offerings,vec![QueryOfferingsResult{
+            id:"3".to_string(),
+            token_id:"Hope.2".to_string(),
+            list_price:Asset { info:AssetInfo::NativeToken{ denom:"ujuno".to_string() },  amount:Uint128::new(2000000) },
+            seller:"buyer2".to_string(),
+            dutch_auction:None,
+            expires_at:None
+        }]);
+
+        let juno_tvl = query_get_tvl(deps.as_ref(),"hope1_address".to_string(),"ujuno".to_string()).unwrap();
+        let hope_tvl = query_get_tvl(deps.as_ref(),"hope1_address".to_string(),"token_address".to_string()).unwrap();
+        let osmos_tvl = query_get_tvl(deps.as_ref(),"hope1_address".to_string(),"osmos".to_string()).unwrap();
+        let raw_tvl  = query_get_tvl(deps.as_ref(),"hope1_address".to_string(),"raw_address".to_string()).unwrap();
+        println!("{}","juno".to_string());
+        assert_eq!(juno_tvl,Uint128::new(1000000));
+        
+        println!("{}","hope".to_string());
+        assert_eq!(hope_tvl,Uint128::new(2000000));
+
+        println!("{}","osmos".to_string());
+        assert_eq!(osmos_tvl,Uint128::new(2000000));
+
+         println!("{}","raw".to_string());
+        assert_eq!(raw_tvl,Uint128::new(2000000));
+
+        // osmos sales no longer count as juno volume
+        let trading_info = query_get_trading(deps.as_ref(), "hope1_address".to_string()).unwrap();
+        assert_eq!(trading_info, PriceInfo { total_juno:Uint128::new(1000000), total_hope:Uint128::new(2000000) });
+        let volumes = query_volumes(deps.as_ref(), "hope1_address".to_string()).unwrap();
+        assert_eq!(volumes.len(), 4);
+        assert_eq!(volumes[0], VolumeInfo {
+            info:AssetInfo::NativeToken{ denom:"osmos".to_string() },
+            amount:Uint128::new(2000000),
+            sales:1
+        });
+
+        let collection_info = query_collection_info(deps.as_ref(), "hope1_address".to_string()).unwrap();
+        assert_eq!(collection_info.sale_id,4);
+        let _sale_history = query_get_history(deps.as_ref(), "hope1_address".to_string(), vec!["1".to_string(),"2".to_string(),"3".to_string(),"4".to_string()]).unwrap();
+        
+        let tvl_all = query_all_tvl(deps.as_ref(), "hope1_address".to_string(), vec!["ujuno".to_string(),"token_address".to_string(),"osmos".to_string(),"xyz".to_string()]).unwrap();
+        assert_eq!(tvl_all,vec![TvlInfo{
+            denom:"ujuno".to_string(),
+            amount:Uint128::new(1000000)
+        },TvlInfo{
+            denom:"token_address".to_string(),
+            amount:Uint128::new(2000000)
+        },TvlInfo{
+            denom:"osmos".to_string(),
+            amount:Uint128::new(2000000)
+        },TvlInfo{
+            denom:"xyz".to_string(),
+            amount:Uint128::new(0)
+        }]);
+        // Registered cw20 symbols resolve to their contract address
+        assert_eq!(query_get_tvl(deps.as_ref(), "hope1_address".to_string(), "hope".to_string()).unwrap(), Uint128::new(2000000));
+
+         let info = mock_info("owner", &[]);
+        let msg = ExecuteMsg::SetTvl { address: "hope1_address".to_string(), tvl: vec![TvlInfo{
+            denom:"ujuno".to_string(),
+            amount:Uint128::new(0)
+        }] };
+        execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+        let tvl_all = query_all_tvl(deps.as_ref(), "hope1_address".to_string(), vec!["ujuno".to_string()]).unwrap();
+        assert_eq!(tvl_all,vec![TvlInfo{
+            denom:"ujuno".to_string(),
+            amount:Uint128::new(0)
+        }]);
+       
+    }
+
+    fn setup_collection(mut deps: DepsMut) {
+        let info = mock_info("creator", &[]);
+        instantiate(deps.branch(), mock_env(), info, InstantiateMsg { owner:"owner".to_string(), fee:None, fee_collector:None }).unwrap();
+
+        let info = mock_info("owner", &[]);
+        let msg = ExecuteMsg::AddTokenAddress { address:"token_address".to_string(), symbol:"hope".to_string() };
+        execute(deps.branch(), mock_env(), info, msg).unwrap();
+
+        let info = mock_info("owner", &[]);
+        let msg = ExecuteMsg::AddCollection {
+            royalty_portion: Decimal::from_ratio(5u128, 100u128),
+            members: vec![UserInfo{
+                address:"admin1".to_string(),
+                portion:Decimal::from_ratio(3u128, 10u128)
+                },UserInfo{
+                address:"admin2".to_string(),
+                portion:Decimal::from_ratio(7u128, 10u128)
+                }] ,
+            nft_address: "hope1_address".to_string() ,
+            offering_id:0,
+            sale_id:0,
+            fee:None,
+            royalty_mode:None
+        };
+        execute(deps, mock_env(), info, msg).unwrap();
+    }
+
+    #[test]
+    fn auction() {
+        let mut deps = mock_dependencies();
+        setup_collection(deps.as_mut());
+
+        // Start a native auction ending in one hour
+        let end_time = mock_env().block.time.seconds() + 3600;
+        let hook = NftHookMsg::StartAuction(StartAuction {
+            reserve_price: Asset { info:AssetInfo::NativeToken{ denom:"ujuno".to_string() }, amount:Uint128::new(1000) },
+            min_increment: Uint128::new(100),
+            end_time
+        });
+        let info = mock_info("hope1_address", &[]);
+        let msg = ExecuteMsg::ReceiveNft(Cw721ReceiveMsg{
+            sender:"seller".to_string(),
+            token_id:"Hope.1".to_string(),
+            msg:to_binary(&hook).unwrap()
+        });
+        execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+        assert_eq!(query_get_auction_ids(deps.as_ref(), "hope1_address".to_string()).unwrap(), vec!["1"]);
+
+        // Below the reserve price
+        let info = mock_info("bidder1", &[Coin{ denom:"ujuno".to_string(), amount:Uint128::new(999) }]);
+        let msg = ExecuteMsg::PlaceBid { nft_address:"hope1_address".to_string(), auction_id:"1".to_string() };
+        let err = execute(deps.as_mut(), mock_env(), info, msg).unwrap_err();
+        assert!(matches!(err, ContractError::BidTooLow {}));
+
+        let info = mock_info("bidder1", &[Coin{ denom:"ujuno".to_string(), amount:Uint128::new(1000) }]);
+        let msg = ExecuteMsg::PlaceBid { nft_address:"hope1_address".to_string(), auction_id:"1".to_string() };
+        let res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+        assert_eq!(res.messages.len(), 0);
+
+        // Below the minimum increment
+        let info = mock_info("bidder2", &[Coin{ denom:"ujuno".to_string(), amount:Uint128::new(1050) }]);
+        let msg = ExecuteMsg::PlaceBid { nft_address:"hope1_address".to_string(), auction_id:"1".to_string() };
+        let err = execute(deps.as_mut(), mock_env(), info, msg).unwrap_err();
+        assert!(matches!(err, ContractError::BidTooLow {}));
+
+        // Outbidding refunds the previous bidder
+        let info = mock_info("bidder2", &[Coin{ denom:"ujuno".to_string(), amount:Uint128::new(2000) }]);
+        let msg = ExecuteMsg::PlaceBid { nft_address:"hope1_address".to_string(), auction_id:"1".to_string() };
+        let res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+        assert_eq!(res.messages[0].msg, CosmosMsg::Bank(BankMsg::Send {
+            to_address:"bidder1".to_string(),
+            amount:vec![Coin{ denom:"ujuno".to_string(), amount:Uint128::new(1000) }]
+        }));
+
+        let msg = ExecuteMsg::SettleAuction { nft_address:"hope1_address".to_string(), auction_id:"1".to_string() };
+        let err = execute(deps.as_mut(), mock_env(), mock_info("anyone", &[]), msg).unwrap_err();
+        assert!(matches!(err, ContractError::AuctionNotEnded {}));
+
+        let mut env = mock_env();
+        env.block.time = env.block.time.plus_seconds(3600);
+
+        let info = mock_info("bidder3", &[Coin{ denom:"ujuno".to_string(), amount:Uint128::new(5000) }]);
+        let msg = ExecuteMsg::PlaceBid { nft_address:"hope1_address".to_string(), auction_id:"1".to_string() };
+        let err = execute(deps.as_mut(), env.clone(), info, msg).unwrap_err();
+        assert!(matches!(err, ContractError::AuctionEnded {}));
+
+        let msg = ExecuteMsg::SettleAuction { nft_address:"hope1_address".to_string(), auction_id:"1".to_string() };
+        let res = execute(deps.as_mut(), env.clone(), mock_info("anyone", &[]), msg).unwrap();
+        assert_eq!(res.messages.len(), 4);
+        assert_eq!(res.messages[0].msg, CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: "hope1_address".to_string(),
+            funds: vec![],
+            msg: to_binary(&Cw721ExecuteMsg::TransferNft {
+                recipient: "bidder2".to_string(),
+                token_id:"Hope.1".to_string(),
+            }).unwrap(),
+        }));
+        assert_eq!(res.messages[1].msg, CosmosMsg::Bank(BankMsg::Send {
+            to_address:"seller".to_string(),
+            amount:vec![Coin{ denom:"ujuno".to_string(), amount:Uint128::new(1900) }]
+        }));
+        assert_eq!(query_get_auction_ids(deps.as_ref(), "hope1_address".to_string()).unwrap().len(), 0);
+        assert_eq!(query_get_tvl(deps.as_ref(), "hope1_address".to_string(), "ujuno".to_string()).unwrap(), Uint128::new(2000));
+        assert_eq!(query_collection_info(deps.as_ref(), "hope1_address".to_string()).unwrap().sale_id, 1);
+
+        // Cw20 auction, bids arrive through the token hook and the seller is paid in tokens
+        let hook = NftHookMsg::StartAuction(StartAuction {
+            reserve_price: Asset { info:AssetInfo::Token{ contract_addr:"token_address".to_string() }, amount:Uint128::new(1000) },
+            min_increment: Uint128::new(100),
+            end_time
+        });
+        let info = mock_info("hope1_address", &[]);
+        let msg = ExecuteMsg::ReceiveNft(Cw721ReceiveMsg{
+            sender:"seller".to_string(),
+            token_id:"Hope.2".to_string(),
+            msg:to_binary(&hook).unwrap()
+        });
+        execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+        let info = mock_info("bidder1", &[Coin{ denom:"hope".to_string(), amount:Uint128::new(1000) }]);
+        let msg = ExecuteMsg::PlaceBid { nft_address:"hope1_address".to_string(), auction_id:"2".to_string() };
+        let err = execute(deps.as_mut(), mock_env(), info, msg).unwrap_err();
+        assert!(matches!(err, ContractError::WrongTokenContractError {}));
+
+        let bid = TokenHookMsg::PlaceBid { nft_address:"hope1_address".to_string(), auction_id:"2".to_string() };
+        let info = mock_info("token_address", &[]);
+        let msg = ExecuteMsg::Receive(Cw20ReceiveMsg{
+            sender:"bidder1".to_string(),
+            amount:Uint128::new(1000),
+            msg:to_binary(&bid).unwrap()
+        });
+        execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+        let info = mock_info("token_address", &[]);
+        let msg = ExecuteMsg::Receive(Cw20ReceiveMsg{
+            sender:"bidder2".to_string(),
+            amount:Uint128::new(1100),
+            msg:to_binary(&bid).unwrap()
+        });
+        let res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+        assert_eq!(res.messages[0].msg, CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: "token_address".to_string(),
+            funds: vec![],
+            msg: to_binary(&Cw20ExecuteMsg::Transfer {
+                recipient: "bidder1".to_string(),
+                amount: Uint128::new(1000),
+            }).unwrap()
+        }));
+
+        let auctions = query_get_auctions(deps.as_ref(), vec!["2".to_string()], "hope1_address".to_string()).unwrap();
+        assert_eq!(auctions[0].highest_bid, Some(Bid { bidder:"bidder2".to_string(), amount:Uint128::new(1100) }));
+
+        let msg = ExecuteMsg::SettleAuction { nft_address:"hope1_address".to_string(), auction_id:"2".to_string() };
+        let res = execute(deps.as_mut(), env, mock_info("anyone", &[]), msg).unwrap();
+        assert_eq!(res.messages[1].msg, CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: "token_address".to_string(),
+            funds: vec![],
+            msg: to_binary(&Cw20ExecuteMsg::Transfer {
+                recipient: "seller".to_string(),
+                amount: Uint128::new(1045),
+            }).unwrap()
+        }));
+    }
+
+    #[test]
+    fn auction_without_bids() {
+        let mut deps = mock_dependencies();
+        setup_collection(deps.as_mut());
+
+        let end_time = mock_env().block.time.seconds() + 3600;
+        let hook = NftHookMsg::StartAuction(StartAuction {
+            reserve_price: Asset { info:AssetInfo::NativeToken{ denom:"ujuno".to_string() }, amount:Uint128::new(1000) },
+            min_increment: Uint128::new(100),
+            end_time
+        });
+        let info = mock_info("hope1_address", &[]);
+        let msg = ExecuteMsg::ReceiveNft(Cw721ReceiveMsg{
+            sender:"seller".to_string(),
+            token_id:"Hope.1".to_string(),
+            msg:to_binary(&hook).unwrap()
+        });
+        execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+        let mut env = mock_env();
+        env.block.time = env.block.time.plus_seconds(3600);
+
+        // Nobody bid, the nft goes back to the seller and nothing is recorded as sold
+        let msg = ExecuteMsg::SettleAuction { nft_address:"hope1_address".to_string(), auction_id:"1".to_string() };
+        let res = execute(deps.as_mut(), env.clone(), mock_info("anyone", &[]), msg.clone()).unwrap();
+        assert_eq!(res.messages.len(), 1);
+        assert_eq!(res.messages[0].msg, CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: "hope1_address".to_string(),
+            funds: vec![],
+            msg: to_binary(&Cw721ExecuteMsg::TransferNft {
+                recipient: "seller".to_string(),
+                token_id: "Hope.1".to_string(),
+            }).unwrap(),
+        }));
+        assert!(query_get_auction_ids(deps.as_ref(), "hope1_address".to_string()).unwrap().is_empty());
+        assert_eq!(query_collection_info(deps.as_ref(), "hope1_address".to_string()).unwrap().sale_id, 0);
 
-pub fn query_get_offering(deps:Deps,ids:Vec<String>,address: String) -> StdResult<Vec<QueryOfferingsResult>>{
-    let mut offering_group:Vec<QueryOfferingsResult> = vec![];
-    for id in ids{
-        let offering = OFFERINGS.may_load(deps.storage,(&address,&id))?;
-        if offering!=None{
-            let offering = offering.unwrap();
-            offering_group.push(QueryOfferingsResult{
-                id:id,
-                token_id:offering.token_id,
-                list_price:offering.list_price,
-                seller:offering.seller
-            });
-        }
+        // The auction is gone once settled
+        execute(deps.as_mut(), env, mock_info("anyone", &[]), msg).unwrap_err();
     }
-    Ok(offering_group)
-}
 
-pub fn query_get_history(deps:Deps,address:String, ids:Vec<String>) -> StdResult<Vec<SaleInfo>>{
-    let mut sale_history : Vec<SaleInfo> = vec![];
-    for id in ids{
-       let history =  SALEHISTORY.may_load(deps.storage, (&address,&id))?;
-       if history != None{
-        sale_history.push(history.unwrap());
-       }
-    }
-    Ok(sale_history)
-}
+    #[test]
+    fn dutch_auction() {
+        let mut deps = mock_dependencies();
+        setup_collection(deps.as_mut());
+
+        // 10000 falling to 2000 over 1000 seconds in 100 second steps
+        let start_time = mock_env().block.time.seconds();
+        let hook = NftHookMsg::StartDutchAuction(StartDutchAuction {
+            start_price: Asset { info:AssetInfo::NativeToken{ denom:"ujuno".to_string() }, amount:Uint128::new(10000) },
+            floor_price: Uint128::new(2000),
+            start_time,
+            end_time: start_time + 1000,
+            step: Some(100)
+        });
+        let info = mock_info("hope1_address", &[]);
+        let msg = ExecuteMsg::ReceiveNft(Cw721ReceiveMsg{
+            sender:"seller".to_string(),
+            token_id:"Hope.1".to_string(),
+            msg:to_binary(&hook).unwrap()
+        });
+        execute(deps.as_mut(), mock_env(), info, msg).unwrap();
 
-#[cfg(test)]
-mod tests {
-  
-    use super::*;
-    use cosmwasm_std::testing::{mock_dependencies, mock_env, mock_info};
-    use cosmwasm_std::{ CosmosMsg, Coin};
+        let mut env = mock_env();
+        env.block.time = env.block.time.plus_seconds(250);
+        let price = query_current_price(deps.as_ref(), env.clone(), "hope1_address".to_string(), "1".to_string()).unwrap();
+        assert_eq!(price.amount, Uint128::new(8400));
+
+        let info = mock_info("buyer", &[Coin{ denom:"ujuno".to_string(), amount:Uint128::new(8000) }]);
+        let msg = ExecuteMsg::BuyNft { offering_id:"1".to_string(), nft_address:"hope1_address".to_string(), token_id:None };
+        let err = execute(deps.as_mut(), env.clone(), info, msg).unwrap_err();
+        assert!(matches!(err, ContractError::NotEnoughFunds {}));
+
+        // Overpayment is refunded to the buyer
+        let info = mock_info("buyer", &[Coin{ denom:"ujuno".to_string(), amount:Uint128::new(9000) }]);
+        let msg = ExecuteMsg::BuyNft { offering_id:"1".to_string(), nft_address:"hope1_address".to_string(), token_id:None };
+        let res = execute(deps.as_mut(), env, info, msg).unwrap();
+        assert_eq!(res.messages.len(), 5);
+        assert_eq!(res.messages[1].msg, CosmosMsg::Bank(BankMsg::Send {
+            to_address:"seller".to_string(),
+            amount:vec![Coin{ denom:"ujuno".to_string(), amount:Uint128::new(7980) }]
+        }));
+        assert_eq!(res.messages[4].msg, CosmosMsg::Bank(BankMsg::Send {
+            to_address:"buyer".to_string(),
+            amount:vec![Coin{ denom:"ujuno".to_string(), amount:Uint128::new(600) }]
+        }));
+        assert_eq!(query_get_tvl(deps.as_ref(), "hope1_address".to_string(), "ujuno".to_string()).unwrap(), Uint128::new(8400));
+
+        // Past the end time the floor price applies
+        let offering = Offering {
+            nft_address:"hope1_address".to_string(),
+            token_id:"Hope.2".to_string(),
+            seller:"seller".to_string(),
+            list_price:Asset { info:AssetInfo::NativeToken{ denom:"ujuno".to_string() }, amount:Uint128::new(10000) },
+            dutch_auction:Some(DutchAuction {
+                floor_price: Uint128::new(2000),
+                start_time,
+                end_time: start_time + 1000,
+                step: None
+            }),
+            expires_at:None
+        };
+        assert_eq!(offering.current_price(start_time + 500), Uint128::new(6000));
+        assert_eq!(offering.current_price(start_time + 5000), Uint128::new(2000));
+    }
 
     #[test]
-    fn testing() {
-        //Instantiate
+    fn token_offer() {
         let mut deps = mock_dependencies();
-        let instantiate_msg = InstantiateMsg {
-           owner:"creator".to_string()
-        };
-        let info = mock_info("creator", &[]);
-        let res = instantiate(deps.as_mut(), mock_env(), info, instantiate_msg).unwrap();
-        assert_eq!(0, res.messages.len());
-        let state = query_state_info(deps.as_ref()).unwrap();
-        assert_eq!(state.owner,"creator".to_string());
-       
+        setup_collection(deps.as_mut());
+        let expires = mock_env().block.time.seconds() + 3600;
 
-        //Change Owner
+        // Offers need escrowed funds matching the price
+        let info = mock_info("bidder1", &[Coin{ denom:"ujuno".to_string(), amount:Uint128::new(500) }]);
+        let msg = ExecuteMsg::MakeOffer {
+            nft_address:"hope1_address".to_string(),
+            token_id:"Hope.1".to_string(),
+            price:Asset { info:AssetInfo::NativeToken{ denom:"ujuno".to_string() }, amount:Uint128::new(1000) },
+            expires
+        };
+        let err = execute(deps.as_mut(), mock_env(), info, msg.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::NotEnoughFunds {}));
 
-        let info = mock_info("creator", &[]);
-        let msg = ExecuteMsg::ChangeOwner { address:"owner".to_string()};
+        let info = mock_info("bidder1", &[Coin{ denom:"ujuno".to_string(), amount:Uint128::new(1000) }]);
         execute(deps.as_mut(), mock_env(), info, msg).unwrap();
-        let state = query_state_info(deps.as_ref()).unwrap();
-        assert_eq!(state.owner,"owner".to_string());
-
-         //Change Token Contract Address
 
-        let info = mock_info("owner", &[]);
-        let msg = ExecuteMsg::AddTokenAddress  { address:"token_address".to_string(),symbol:"hope".to_string()};
-        execute(deps.as_mut(), mock_env(), info, msg).unwrap();
-        
-        let info = mock_info("owner", &[]);
-         let msg = ExecuteMsg::AddTokenAddress  { address:"raw_address".to_string(),symbol:"raw".to_string()};
-        execute(deps.as_mut(), mock_env(), info, msg).unwrap();
-       
-        //Hope1 Collection Add
-       let info = mock_info("owner", &[]);
-       let msg = ExecuteMsg::AddCollection {
-            royalty_portion: Decimal::from_ratio(5 as u128, 100 as u128), 
-            members: vec![UserInfo{
-                address:"admin1".to_string(),
-                portion:Decimal::from_ratio(3 as u128, 10 as u128)
-                },UserInfo{
-                address:"admin2".to_string(),
-                portion:Decimal::from_ratio(7 as u128, 10 as u128)
-                }] ,
-            nft_address: "hope1_address".to_string() ,
-            offering_id:0,
-            sale_id:0
+        let hook = TokenHookMsg::MakeOffer {
+            nft_address:"hope1_address".to_string(),
+            token_id:"Hope.1".to_string(),
+            expires
         };
+        let info = mock_info("token_address", &[]);
+        let msg = ExecuteMsg::Receive(Cw20ReceiveMsg{
+            sender:"bidder2".to_string(),
+            amount:Uint128::new(3000),
+            msg:to_binary(&hook).unwrap()
+        });
         execute(deps.as_mut(), mock_env(), info, msg).unwrap();
-       
 
-       // Sell nft
-        let cw721_msg = SellNft{
-            list_price:Asset{
-                denom:"ujuno".to_string(),
-                amount:Uint128::new(1000000)
-            }
-        };
+        let offers = query_token_offers(deps.as_ref(), "hope1_address".to_string(), "Hope.1".to_string()).unwrap();
+        assert_eq!(offers.len(), 2);
+        let offers = query_bidder_offers(deps.as_ref(), "bidder2".to_string()).unwrap();
+        assert_eq!(offers, vec![TokenOffer {
+            nft_address:"hope1_address".to_string(),
+            token_id:"Hope.1".to_string(),
+            bidder:"bidder2".to_string(),
+            price:Asset { info:AssetInfo::Token{ contract_addr:"token_address".to_string() }, amount:Uint128::new(3000) },
+            expires
+        }]);
 
-        let info = mock_info("hope1_address", &[]);
+        // Cancelling refunds the bidder
+        let msg = ExecuteMsg::CancelOffer { nft_address:"hope1_address".to_string(), token_id:"Hope.1".to_string() };
+        let res = execute(deps.as_mut(), mock_env(), mock_info("bidder1", &[]), msg).unwrap();
+        assert_eq!(res.messages[0].msg, CosmosMsg::Bank(BankMsg::Send {
+            to_address:"bidder1".to_string(),
+            amount:vec![Coin{ denom:"ujuno".to_string(), amount:Uint128::new(1000) }]
+        }));
+
+        // The owner accepts by sending the nft
+        let hook = NftHookMsg::AcceptOffer { bidder:"bidder2".to_string() };
         let msg = ExecuteMsg::ReceiveNft(Cw721ReceiveMsg{
-            sender:"owner1".to_string(),
+            sender:"holder".to_string(),
             token_id:"Hope.1".to_string(),
-            msg:to_binary(&cw721_msg).unwrap()
+            msg:to_binary(&hook).unwrap()
         });
-        execute(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
+        let mut env = mock_env();
+        env.block.time = env.block.time.plus_seconds(3600);
+        let err = execute(deps.as_mut(), env, mock_info("hope1_address", &[]), msg.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::OfferExpired {}));
+
+        let res = execute(deps.as_mut(), mock_env(), mock_info("hope1_address", &[]), msg).unwrap();
+        assert_eq!(res.messages.len(), 4);
+        assert_eq!(res.messages[0].msg, CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: "hope1_address".to_string(),
+            funds: vec![],
+            msg: to_binary(&Cw721ExecuteMsg::TransferNft {
+                recipient: "bidder2".to_string(),
+                token_id:"Hope.1".to_string(),
+            }).unwrap(),
+        }));
+        assert_eq!(res.messages[1].msg, CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: "token_address".to_string(),
+            funds: vec![],
+            msg: to_binary(&Cw20ExecuteMsg::Transfer {
+                recipient: "holder".to_string(),
+                amount: Uint128::new(2850),
+            }).unwrap()
+        }));
 
+        let history = query_get_history(deps.as_ref(), "hope1_address".to_string(), vec!["1".to_string()]).unwrap();
+        assert_eq!(history[0].from, "holder".to_string());
+        assert_eq!(history[0].to, "bidder2".to_string());
+        assert!(query_token_offers(deps.as_ref(), "hope1_address".to_string(), "Hope.1".to_string()).unwrap().is_empty());
+    }
 
-        let collection_info = query_collection_info(deps.as_ref(),
-                "hope1_address".to_string()).unwrap();
-        assert_eq!(collection_info,CollectionInfo{
+    #[test]
+    fn collection_offer() {
+        let mut deps = mock_dependencies();
+        setup_collection(deps.as_mut());
+        let expires = mock_env().block.time.seconds() + 3600;
+
+        let info = mock_info("bidder1", &[Coin{ denom:"ujuno".to_string(), amount:Uint128::new(2000) }]);
+        let msg = ExecuteMsg::MakeCollectionOffer {
             nft_address:"hope1_address".to_string(),
-            offering_id:1,
-            royalty_portion:Decimal::from_ratio(5 as u128, 100 as u128),
-            sale_id:0
+            price:Asset { info:AssetInfo::NativeToken{ denom:"ujuno".to_string() }, amount:Uint128::new(1000) },
+            quantity:Some(3),
+            expires
+        };
+        let err = execute(deps.as_mut(), mock_env(), info, msg.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::NotEnoughFunds {}));
+
+        let info = mock_info("bidder1", &[Coin{ denom:"ujuno".to_string(), amount:Uint128::new(3000) }]);
+        execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+        // Any holder can fill one unit of the offer
+        for (holder, token_id) in [("holder1", "Hope.1"), ("holder2", "Hope.2")] {
+            let msg = ExecuteMsg::ReceiveNft(Cw721ReceiveMsg{
+                sender:holder.to_string(),
+                token_id:token_id.to_string(),
+                msg:to_binary(&NftHookMsg::AcceptCollectionOffer { bidder:"bidder1".to_string() }).unwrap()
             });
+            let res = execute(deps.as_mut(), mock_env(), mock_info("hope1_address", &[]), msg).unwrap();
+            assert_eq!(res.messages[1].msg, CosmosMsg::Bank(BankMsg::Send {
+                to_address:holder.to_string(),
+                amount:vec![Coin{ denom:"ujuno".to_string(), amount:Uint128::new(950) }]
+            }));
+        }
 
-      
-        let offerings = query_get_offering(deps.as_ref(),vec!["1".to_string(),"2".to_string()],"hope1_address".to_string()).unwrap();
-        assert_eq!(offerings,vec![QueryOfferingsResult{
-            id:"1".to_string(),
-            token_id:"Hope.1".to_string(),
-            list_price:Asset { denom: "ujuno".to_string(), amount: Uint128::new(1000000) },
-            seller:"owner1".to_string()
-        }]);
+        let offers = query_collection_offers(deps.as_ref(), "hope1_address".to_string()).unwrap();
+        assert_eq!(offers[0].quantity, 1);
+        assert_eq!(query_collection_info(deps.as_ref(), "hope1_address".to_string()).unwrap().sale_id, 2);
 
-            //Buy nft
+        // Cancelling refunds the unfilled quantity
+        let msg = ExecuteMsg::CancelCollectionOffer { nft_address:"hope1_address".to_string() };
+        let res = execute(deps.as_mut(), mock_env(), mock_info("bidder1", &[]), msg).unwrap();
+        assert_eq!(res.messages[0].msg, CosmosMsg::Bank(BankMsg::Send {
+            to_address:"bidder1".to_string(),
+            amount:vec![Coin{ denom:"ujuno".to_string(), amount:Uint128::new(1000) }]
+        }));
 
-      let info = mock_info("test_buyer1", &[Coin{
-        denom:"ujuno".to_string(),
-        amount:Uint128::new(1000000)
-      }]);
-      let msg = ExecuteMsg::BuyNft { offering_id: "1".to_string(), nft_address: "hope1_address".to_string() };
-      let res = execute(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
-      assert_eq!(res.messages.len(),4);
+        // Cw20 offers split the sent amount over the quantity
+        let hook = TokenHookMsg::MakeCollectionOffer { nft_address:"hope1_address".to_string(), quantity:Some(2), expires };
+        let msg = ExecuteMsg::Receive(Cw20ReceiveMsg{
+            sender:"bidder2".to_string(),
+            amount:Uint128::new(4000),
+            msg:to_binary(&hook).unwrap()
+        });
+        execute(deps.as_mut(), mock_env(), mock_info("token_address", &[]), msg).unwrap();
+        let offers = query_collection_offers(deps.as_ref(), "hope1_address".to_string()).unwrap();
+        assert_eq!(offers, vec![CollectionOffer {
+            nft_address:"hope1_address".to_string(),
+            bidder:"bidder2".to_string(),
+            price:Asset { info:AssetInfo::Token{ contract_addr:"token_address".to_string() }, amount:Uint128::new(2000) },
+            quantity:2,
+            expires
+        }]);
+    }
 
-       let collection_info = query_collection_info(deps.as_ref(),"hope1_address".to_string()).unwrap();
-       assert_eq!(collection_info.offering_id,0); 
+    #[test]
+    fn offering_expiration() {
+        let mut deps = mock_dependencies();
+        setup_collection(deps.as_mut());
+        let expires_at = mock_env().block.time.seconds() + 600;
+
+        for token_id in ["Hope.1", "Hope.2"] {
+            let cw721_msg = SellNft{
+                list_price:Asset { info:AssetInfo::NativeToken{ denom:"ujuno".to_string() }, amount:Uint128::new(1000) },
+                expires_at:Some(expires_at)
+            };
+            let msg = ExecuteMsg::ReceiveNft(Cw721ReceiveMsg{
+                sender:"seller".to_string(),
+                token_id:token_id.to_string(),
+                msg:to_binary(&cw721_msg).unwrap()
+            });
+            execute(deps.as_mut(), mock_env(), mock_info("hope1_address", &[]), msg).unwrap();
+        }
 
-      assert_eq!(res.messages[0].msg,CosmosMsg::Wasm(WasmMsg::Execute {
-                contract_addr: "hope1_address".to_string(),
-                funds: vec![],
-                msg: to_binary(&Cw721ExecuteMsg::TransferNft {
-                    recipient: "test_buyer1".to_string(),
-                    token_id:"Hope.1".to_string(),
+        let msg = ExecuteMsg::ReclaimExpired { nft_address:"hope1_address".to_string(), offering_id:"1".to_string() };
+        let err = execute(deps.as_mut(), mock_env(), mock_info("anyone", &[]), msg).unwrap_err();
+        assert!(matches!(err, ContractError::NotExpired {}));
+
+        let mut env = mock_env();
+        env.block.time = env.block.time.plus_seconds(600);
+
+        let info = mock_info("buyer", &[Coin{ denom:"ujuno".to_string(), amount:Uint128::new(1000) }]);
+        let msg = ExecuteMsg::BuyNft { offering_id:"1".to_string(), nft_address:"hope1_address".to_string(), token_id:None };
+        let err = execute(deps.as_mut(), env.clone(), info, msg).unwrap_err();
+        assert!(matches!(err, ContractError::OfferingExpired {}));
+
+        let ids = vec!["1".to_string(), "2".to_string()];
+        let offerings = query_get_offering(deps.as_ref(), env.clone(), ids.clone(), "hope1_address".to_string(), true).unwrap();
+        assert!(offerings.is_empty());
+        let offerings = query_get_offering(deps.as_ref(), env.clone(), ids, "hope1_address".to_string(), false).unwrap();
+        assert_eq!(offerings.len(), 2);
+
+        let msg = ExecuteMsg::ReclaimExpired { nft_address:"hope1_address".to_string(), offering_id:"1".to_string() };
+        let res = execute(deps.as_mut(), env, mock_info("anyone", &[]), msg).unwrap();
+        assert_eq!(res.messages[0].msg, CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: "hope1_address".to_string(),
+            funds: vec![],
+            msg: to_binary(&Cw721ExecuteMsg::TransferNft {
+                recipient: "seller".to_string(),
+                token_id:"Hope.1".to_string(),
             }).unwrap(),
         }));
+        assert_eq!(query_get_ids(deps.as_ref(), "hope1_address".to_string()).unwrap(), vec!["2"]);
 
-      assert_eq!(res.messages[1].msg,CosmosMsg::Bank(BankMsg::Send {
-                to_address: "owner1".to_string(),
-                amount:vec![Coin{
-                    denom:"ujuno".to_string(),
-                    amount:Uint128::new(950000)
-                }]
-        }));
+        // Offerings only exist for registered collections
+        let msg = ExecuteMsg::SetOfferings { address:"unknown_address".to_string(), offering:vec![QueryOfferingsResult {
+            id:"1".to_string(),
+            token_id:"Hope.1".to_string(),
+            list_price:Asset { info:AssetInfo::NativeToken{ denom:"ujuno".to_string() }, amount:Uint128::new(1000) },
+            seller:"seller".to_string(),
+            dutch_auction:None,
+            expires_at:Some(expires_at)
+        }] };
+        let err = execute(deps.as_mut(), mock_env(), mock_info("owner", &[]), msg).unwrap_err();
+        assert!(matches!(err, ContractError::WrongNFTContractError {}));
+        let err = remove_offering(deps.as_mut().storage, "unknown_address", 1).unwrap_err();
+        assert!(matches!(err, ContractError::WrongNFTContractError {}));
+    }
 
-        assert_eq!(res.messages[2].msg,CosmosMsg::Bank(BankMsg::Send {
-                to_address: "admin1".to_string(),
-                amount:vec![Coin{
-                    denom:"ujuno".to_string(),
-                    amount:Uint128::new(15000)
-                }]
+    #[test]
+    fn update_offering() {
+        let mut deps = mock_dependencies();
+        setup_collection(deps.as_mut());
+
+        let cw721_msg = SellNft{
+            list_price:Asset { info:AssetInfo::NativeToken{ denom:"ujuno".to_string() }, amount:Uint128::new(1000) },
+            expires_at:None
+        };
+        let msg = ExecuteMsg::ReceiveNft(Cw721ReceiveMsg{
+            sender:"seller".to_string(),
+            token_id:"Hope.1".to_string(),
+            msg:to_binary(&cw721_msg).unwrap()
+        });
+        execute(deps.as_mut(), mock_env(), mock_info("hope1_address", &[]), msg).unwrap();
+
+        let msg = ExecuteMsg::UpdateOffering {
+            nft_address:"hope1_address".to_string(),
+            offering_id:"1".to_string(),
+            list_price:Asset { info:AssetInfo::Token{ contract_addr:"token_address".to_string() }, amount:Uint128::new(500) }
+        };
+        let err = execute(deps.as_mut(), mock_env(), mock_info("someone", &[]), msg.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized {}));
+
+        let res = execute(deps.as_mut(), mock_env(), mock_info("seller", &[]), msg).unwrap();
+        assert_eq!(res.attributes[4].value, "1000ujuno");
+        assert_eq!(res.attributes[5].value, "500token_address");
+
+        let offerings = query_get_offering(deps.as_ref(), mock_env(), vec!["1".to_string()], "hope1_address".to_string(), false).unwrap();
+        assert_eq!(offerings[0].list_price, Asset { info:AssetInfo::Token{ contract_addr:"token_address".to_string() }, amount:Uint128::new(500) });
+        assert_eq!(offerings[0].token_id, "Hope.1".to_string());
+    }
+
+    #[test]
+    fn marketplace_fee() {
+        let mut deps = mock_dependencies();
+        setup_collection(deps.as_mut());
+
+        let fee_info = query_fee_info(deps.as_ref()).unwrap();
+        assert_eq!(fee_info.fee, Decimal::zero());
+        assert_eq!(fee_info.fee_collector, "owner".to_string());
+
+        let msg = ExecuteMsg::UpdateConfig { fee:Some(Decimal::one()), fee_collector:None, fee_tiers:None };
+        let err = execute(deps.as_mut(), mock_env(), mock_info("owner", &[]), msg).unwrap_err();
+        assert!(matches!(err, ContractError::InvalidFee {}));
+
+        let msg = ExecuteMsg::UpdateConfig {
+            fee:Some(Decimal::from_ratio(2u128, 100u128)),
+            fee_collector:Some("treasury".to_string()),
+            fee_tiers:None
+        };
+        let err = execute(deps.as_mut(), mock_env(), mock_info("someone", &[]), msg.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::MissingRole { role: Role::Treasury }));
+        execute(deps.as_mut(), mock_env(), mock_info("owner", &[]), msg).unwrap();
+
+        let cw721_msg = SellNft{
+            list_price:Asset { info:AssetInfo::NativeToken{ denom:"ujuno".to_string() }, amount:Uint128::new(1000) },
+            expires_at:None
+        };
+        let msg = ExecuteMsg::ReceiveNft(Cw721ReceiveMsg{
+            sender:"seller".to_string(),
+            token_id:"Hope.1".to_string(),
+            msg:to_binary(&cw721_msg).unwrap()
+        });
+        execute(deps.as_mut(), mock_env(), mock_info("hope1_address", &[]), msg).unwrap();
+
+        let msg = ExecuteMsg::BuyNft { offering_id:"1".to_string(), nft_address:"hope1_address".to_string(), token_id:None };
+        let res = execute(deps.as_mut(), mock_env(), mock_info("buyer", &coins(1000, "ujuno")), msg).unwrap();
+        assert_eq!(res.messages.len(), 5);
+        // 5% royalty and 2% fee come out of the seller share
+        assert_eq!(res.messages[1].msg, CosmosMsg::Bank(BankMsg::Send {
+            to_address:"seller".to_string(),
+            amount:coins(930, "ujuno")
         }));
-        assert_eq!(res.messages[3].msg,CosmosMsg::Bank(BankMsg::Send {
-                to_address: "admin2".to_string(),
-                amount:vec![Coin{
-                    denom:"ujuno".to_string(),
-                    amount:Uint128::new(35000)
-                }]
+        assert_eq!(res.messages[4].msg, CosmosMsg::Bank(BankMsg::Send {
+            to_address:"treasury".to_string(),
+            amount:coins(20, "ujuno")
         }));
-                                                                    
-        let ids =  query_get_ids(deps.as_ref(),"hope1_address".to_string()).unwrap();
-        let test_id:Vec<String> = vec![];
-        assert_eq!(ids,test_id);
-        
 
-         // Rearragnge the offering
+        // 4% collection override, halved once the collection traded 1000ujuno
+        let msg = ExecuteMsg::UpdateConfig {
+            fee:None,
+            fee_collector:None,
+            fee_tiers:Some(vec![FeeTier {
+                denom:"ujuno".to_string(),
+                min_volume:Uint128::new(1000),
+                discount:Decimal::percent(50)
+            }, FeeTier {
+                denom:"ujuno".to_string(),
+                min_volume:Uint128::new(5000),
+                discount:Decimal::percent(80)
+            }])
+        };
+        execute(deps.as_mut(), mock_env(), mock_info("owner", &[]), msg).unwrap();
+        let msg = ExecuteMsg::UpdateCollection {
+            royalty_portion:Decimal::from_ratio(5u128, 100u128),
+            members:vec![UserInfo{ address:"admin1".to_string(), portion:Decimal::one() }],
+            nft_address:"hope1_address".to_string(),
+            fee:Some(Decimal::percent(4)),
+            clear_fee:None,
+            royalty_mode:None
+        };
+        execute(deps.as_mut(), mock_env(), mock_info("owner", &[]), msg).unwrap();
+
+        let fee = query_effective_fee(deps.as_ref(), "hope1_address".to_string(), "ujuno".to_string()).unwrap();
+        assert_eq!(fee, Decimal::percent(2));
+        let fee = query_effective_fee(deps.as_ref(), "hope1_address".to_string(), "hope".to_string()).unwrap();
+        assert_eq!(fee, Decimal::percent(4));
 
-         //sell
-        let cw721_msg = SellNft{
-            list_price:Asset{
-                denom:"osmos".to_string(),
-                amount:Uint128::new(2000000)
+        // Unrelated updates keep the override, it is only dropped on request
+        let update = |clear_fee: Option<bool>| ExecuteMsg::UpdateCollection {
+            royalty_portion:Decimal::from_ratio(5u128, 100u128),
+            members:vec![UserInfo{ address:"admin1".to_string(), portion:Decimal::one() }],
+            nft_address:"hope1_address".to_string(),
+            fee:None,
+            clear_fee,
+            royalty_mode:Some(RoyaltyMode::Static)
+        };
+        execute(deps.as_mut(), mock_env(), mock_info("owner", &[]), update(None)).unwrap();
+        assert_eq!(query_collection_info(deps.as_ref(), "hope1_address".to_string()).unwrap().fee, Some(Decimal::percent(4)));
+        execute(deps.as_mut(), mock_env(), mock_info("owner", &[]), update(Some(true))).unwrap();
+        assert_eq!(query_collection_info(deps.as_ref(), "hope1_address".to_string()).unwrap().fee, None);
+        let fee = query_effective_fee(deps.as_ref(), "hope1_address".to_string(), "hope".to_string()).unwrap();
+        assert_eq!(fee, Decimal::percent(2));
+    }
+
+    #[test]
+    fn cw2981_royalty() {
+        let mut deps = mock_dependencies();
+        setup_collection(deps.as_mut());
+
+        // The nft contract asks for a 10% royalty paid to the artist
+        deps.querier.update_wasm(|query| match query {
+            WasmQuery::Smart { msg, .. } => {
+                let Cw2981QueryMsg::Extension { msg: RoyaltyQueryMsg::RoyaltyInfo { sale_price, .. } } = from_binary(msg).unwrap();
+                SystemResult::Ok(ContractResult::Ok(to_binary(&RoyaltiesInfoResponse {
+                    address:"artist".to_string(),
+                    royalty_amount:sale_price * Decimal::percent(10)
+                }).unwrap()))
+            }
+            _ => SystemResult::Err(SystemError::UnsupportedRequest { kind:"wasm".to_string() })
+        });
+
+        for (offering_id, royalty_mode, royalty) in [("1", RoyaltyMode::Cw2981Capped, 50), ("2", RoyaltyMode::Cw2981, 100)] {
+            let msg = ExecuteMsg::UpdateCollection {
+                royalty_portion:Decimal::from_ratio(5u128, 100u128),
+                members:vec![UserInfo{ address:"admin1".to_string(), portion:Decimal::one() }],
+                nft_address:"hope1_address".to_string(),
+                fee:None,
+                clear_fee:None,
+                royalty_mode:Some(royalty_mode)
+            };
+            execute(deps.as_mut(), mock_env(), mock_info("owner", &[]), msg).unwrap();
+
+            let cw721_msg = SellNft{
+                list_price:Asset { info:AssetInfo::NativeToken{ denom:"ujuno".to_string() }, amount:Uint128::new(1000) },
+                expires_at:None
+            };
+            let msg = ExecuteMsg::ReceiveNft(Cw721ReceiveMsg{
+                sender:"seller".to_string(),
+                token_id:format!("Hope.{}", offering_id),
+                msg:to_binary(&cw721_msg).unwrap()
+            });
+            execute(deps.as_mut(), mock_env(), mock_info("hope1_address", &[]), msg).unwrap();
+
+            let msg = ExecuteMsg::BuyNft { offering_id:offering_id.to_string(), nft_address:"hope1_address".to_string(), token_id:None };
+            let res = execute(deps.as_mut(), mock_env(), mock_info("buyer", &coins(1000, "ujuno")), msg).unwrap();
+            assert_eq!(res.messages.len(), 3);
+            assert_eq!(res.messages[1].msg, CosmosMsg::Bank(BankMsg::Send {
+                to_address:"seller".to_string(),
+                amount:coins(1000 - royalty, "ujuno")
+            }));
+            assert_eq!(res.messages[2].msg, CosmosMsg::Bank(BankMsg::Send {
+                to_address:"artist".to_string(),
+                amount:coins(royalty, "ujuno")
+            }));
+        }
+    }
+
+    #[test]
+    fn cw2981_invalid_recipient() {
+        let mut deps = mock_dependencies();
+        setup_collection(deps.as_mut());
+
+        deps.querier.update_wasm(|query| match query {
+            WasmQuery::Smart { msg, .. } => {
+                let Cw2981QueryMsg::Extension { msg: RoyaltyQueryMsg::RoyaltyInfo { sale_price, .. } } = from_binary(msg).unwrap();
+                SystemResult::Ok(ContractResult::Ok(to_binary(&RoyaltiesInfoResponse {
+                    address:"Not An Address!".to_string(),
+                    royalty_amount:sale_price * Decimal::percent(10)
+                }).unwrap()))
             }
+            _ => SystemResult::Err(SystemError::UnsupportedRequest { kind:"wasm".to_string() })
+        });
+        let msg = ExecuteMsg::UpdateCollection {
+            royalty_portion:Decimal::percent(5),
+            members:vec![UserInfo{ address:"admin1".to_string(), portion:Decimal::one() }],
+            nft_address:"hope1_address".to_string(),
+            fee:None,
+            clear_fee:None,
+            royalty_mode:Some(RoyaltyMode::Cw2981)
         };
+        execute(deps.as_mut(), mock_env(), mock_info("owner", &[]), msg).unwrap();
 
-        let info = mock_info("hope1_address", &[]);
         let msg = ExecuteMsg::ReceiveNft(Cw721ReceiveMsg{
-            sender:"buyer1".to_string(),
+            sender:"seller".to_string(),
             token_id:"Hope.1".to_string(),
-            msg:to_binary(&cw721_msg).unwrap()
+            msg:to_binary(&SellNft{
+                list_price:Asset { info:AssetInfo::NativeToken{ denom:"ujuno".to_string() }, amount:Uint128::new(1000) },
+                expires_at:None
+            }).unwrap()
         });
-         execute(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
-     
-         //sell
-         let cw721_msg = SellNft{
-            list_price:Asset{
-                denom:"ujuno".to_string(),
-                amount:Uint128::new(2000000)
+        execute(deps.as_mut(), mock_env(), mock_info("hope1_address", &[]), msg).unwrap();
+
+        // The sale still settles, paying the static 5% to the members
+        let msg = ExecuteMsg::BuyNft { offering_id:"1".to_string(), nft_address:"hope1_address".to_string(), token_id:None };
+        let res = execute(deps.as_mut(), mock_env(), mock_info("buyer", &coins(1000, "ujuno")), msg).unwrap();
+        assert_eq!(res.messages.len(), 3);
+        assert_eq!(res.messages[1].msg, CosmosMsg::Bank(BankMsg::Send {
+            to_address:"seller".to_string(),
+            amount:coins(950, "ujuno")
+        }));
+        assert_eq!(res.messages[2].msg, CosmosMsg::Bank(BankMsg::Send {
+            to_address:"admin1".to_string(),
+            amount:coins(50, "ujuno")
+        }));
+    }
+    #[test]
+    fn royalty_capped_by_fee() {
+        let mut deps = mock_dependencies();
+        setup_collection(deps.as_mut());
+        let msg = ExecuteMsg::UpdateConfig { fee:Some(Decimal::percent(2)), fee_collector:None, fee_tiers:None };
+        execute(deps.as_mut(), mock_env(), mock_info("owner", &[]), msg).unwrap();
+
+        // The nft contract claims the whole price as royalty
+        deps.querier.update_wasm(|query| match query {
+            WasmQuery::Smart { msg, .. } => {
+                let Cw2981QueryMsg::Extension { msg: RoyaltyQueryMsg::RoyaltyInfo { sale_price, .. } } = from_binary(msg).unwrap();
+                SystemResult::Ok(ContractResult::Ok(to_binary(&RoyaltiesInfoResponse {
+                    address:"artist".to_string(),
+                    royalty_amount:sale_price
+                }).unwrap()))
             }
+            _ => SystemResult::Err(SystemError::UnsupportedRequest { kind:"wasm".to_string() })
+        });
+        let msg = ExecuteMsg::UpdateCollection {
+            royalty_portion:Decimal::percent(5),
+            members:vec![UserInfo{ address:"admin1".to_string(), portion:Decimal::one() }],
+            nft_address:"hope1_address".to_string(),
+            fee:None,
+            clear_fee:None,
+            royalty_mode:Some(RoyaltyMode::Cw2981)
+        };
+        execute(deps.as_mut(), mock_env(), mock_info("owner", &[]), msg).unwrap();
+
+        let end_time = mock_env().block.time.seconds() + 3600;
+        let hook = NftHookMsg::StartAuction(StartAuction {
+            reserve_price: Asset { info:AssetInfo::NativeToken{ denom:"ujuno".to_string() }, amount:Uint128::new(1000) },
+            min_increment: Uint128::new(100),
+            end_time
+        });
+        let msg = ExecuteMsg::ReceiveNft(Cw721ReceiveMsg{
+            sender:"seller".to_string(),
+            token_id:"Hope.1".to_string(),
+            msg:to_binary(&hook).unwrap()
+        });
+        execute(deps.as_mut(), mock_env(), mock_info("hope1_address", &[]), msg).unwrap();
+        let msg = ExecuteMsg::PlaceBid { nft_address:"hope1_address".to_string(), auction_id:"1".to_string() };
+        execute(deps.as_mut(), mock_env(), mock_info("bidder", &coins(1000, "ujuno")), msg).unwrap();
+
+        let mut env = mock_env();
+        env.block.time = env.block.time.plus_seconds(3600);
+        let msg = ExecuteMsg::SettleAuction { nft_address:"hope1_address".to_string(), auction_id:"1".to_string() };
+        let res = execute(deps.as_mut(), env, mock_info("anyone", &[]), msg).unwrap();
+        assert_eq!(res.messages.len(), 3);
+        assert_eq!(res.messages[1].msg, CosmosMsg::Bank(BankMsg::Send {
+            to_address:"artist".to_string(),
+            amount:coins(980, "ujuno")
+        }));
+        assert_eq!(res.messages[2].msg, CosmosMsg::Bank(BankMsg::Send {
+            to_address:"owner".to_string(),
+            amount:coins(20, "ujuno")
+        }));
+
+        // Static royalties leaving less than the fee are capped the same way
+        let msg = ExecuteMsg::UpdateCollection {
+            royalty_portion:Decimal::percent(99),
+            members:vec![UserInfo{ address:"admin1".to_string(), portion:Decimal::one() }],
+            nft_address:"hope1_address".to_string(),
+            fee:None,
+            clear_fee:None,
+            royalty_mode:Some(RoyaltyMode::Static)
         };
-
-          let info = mock_info("hope1_address", &[]);
-          let msg = ExecuteMsg::ReceiveNft(Cw721ReceiveMsg{
-            sender:"buyer2".to_string(),
+        execute(deps.as_mut(), mock_env(), mock_info("owner", &[]), msg).unwrap();
+        let msg = ExecuteMsg::ReceiveNft(Cw721ReceiveMsg{
+            sender:"seller".to_string(),
             token_id:"Hope.2".to_string(),
-            msg:to_binary(&cw721_msg).unwrap()
-            });
-         execute(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
-
-         //buy
+            msg:to_binary(&SellNft{
+                list_price:Asset { info:AssetInfo::NativeToken{ denom:"ujuno".to_string() }, amount:Uint128::new(1000) },
+                expires_at:None
+            }).unwrap()
+        });
+        execute(deps.as_mut(), mock_env(), mock_info("hope1_address", &[]), msg).unwrap();
+        let msg = ExecuteMsg::BuyNft { offering_id:"1".to_string(), nft_address:"hope1_address".to_string(), token_id:None };
+        let res = execute(deps.as_mut(), mock_env(), mock_info("buyer", &coins(1000, "ujuno")), msg).unwrap();
+        assert_eq!(res.messages.len(), 3);
+        assert_eq!(res.messages[1].msg, CosmosMsg::Bank(BankMsg::Send {
+            to_address:"admin1".to_string(),
+            amount:coins(980, "ujuno")
+        }));
+    }
+    #[test]
+    fn migrate_offering_ids() {
+        let mut deps = mock_dependencies();
+        setup_collection(deps.as_mut());
+
+        set_contract_version(deps.as_mut().storage, CONTRACT_NAME, "0.1.0").unwrap();
+        // Offerings stored by the previous version, packed into ids 1..=offering_id
+        for (id, token_id) in [("1", "Hope.7"), ("2", "Hope.3")] {
+            STORED_OFFERINGS.save(deps.as_mut().storage, ("hope1_address", id), &StoredOffering {
+                token_id:token_id.to_string(),
+                seller:"seller".to_string(),
+                list_price:StoredPrice { info:Some(AssetInfo::NativeToken{ denom:"ujuno".to_string() }), denom:None, amount:Uint128::new(1000) },
+                dutch_auction:None,
+                expires_at:None
+            }).unwrap();
+        }
+        COLLECTIONINFO.update(deps.as_mut().storage, "hope1_address", |collection_info| -> StdResult<_> {
+            let mut collection_info = collection_info.unwrap();
+            collection_info.offering_id = 2;
+            Ok(collection_info)
+        }).unwrap();
 
-        let info = mock_info("test_buyer2", &[Coin{
-            denom:"osmos".to_string(),
-            amount:Uint128::new(2000000)
-        }]);
-        let msg = ExecuteMsg::BuyNft { offering_id: "1".to_string(), nft_address: "hope1_address".to_string() };
-        execute(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
+        migrate(deps.as_mut(), mock_env(), MigrateMsg {}).unwrap();
+        let collection_info = query_collection_info(deps.as_ref(), "hope1_address".to_string()).unwrap();
+        assert_eq!(collection_info.offering_id, 2);
+        assert_eq!(collection_info.num_offerings, 2);
+        let offering = query_offering_by_token(deps.as_ref(), "hope1_address".to_string(), "Hope.3".to_string()).unwrap();
+        assert_eq!(offering.unwrap().id, "2".to_string());
 
-        let id = query_get_ids(deps.as_ref(), "hope1_address".to_string()).unwrap();
-        let collection_info = query_collection_info(deps.as_ref(),"hope1_address".to_string()).unwrap();
-        assert_eq!(collection_info.offering_id,1);  
-        assert_eq!(id,vec!["1"]);
+        let msg = ExecuteMsg::WithdrawNft { offering_id:"1".to_string(), nft_address:"hope1_address".to_string() };
+        execute(deps.as_mut(), mock_env(), mock_info("seller", &[]), msg).unwrap();
 
-         let cw721_msg = SellNft{
-            list_price:Asset{
-                denom:"hope".to_string(),
-                amount:Uint128::new(2000000)
-            }
+        let cw721_msg = SellNft{
+            list_price:Asset { info:AssetInfo::NativeToken{ denom:"ujuno".to_string() }, amount:Uint128::new(1000) },
+            expires_at:None
         };
-
-        let info = mock_info("hope1_address", &[]);
         let msg = ExecuteMsg::ReceiveNft(Cw721ReceiveMsg{
-            sender:"buyer3".to_string(),
-            token_id:"Hope.3".to_string(),
+            sender:"seller".to_string(),
+            token_id:"Hope.8".to_string(),
             msg:to_binary(&cw721_msg).unwrap()
         });
+        execute(deps.as_mut(), mock_env(), mock_info("hope1_address", &[]), msg).unwrap();
 
-        execute(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
-
-        let ids = query_get_ids(deps.as_ref(),"hope1_address".to_string()).unwrap();
-        assert_eq!(ids,vec!["1".to_string(),"2".to_string()]);
-        let offerings = query_get_offering(deps.as_ref(),vec!["1".to_string(),"2".to_string()],"hope1_address".to_string()).unwrap();
-        assert_eq!(offerings,vec![QueryOfferingsResult{
-            id:"1".to_string(),
-            token_id:"Hope.2".to_string(),
-            list_price:Asset { denom: "ujuno".to_string(),  amount:Uint128::new(2000000) },
-            seller:"buyer2".to_string()
-        },QueryOfferingsResult{
-            id:"2".to_string(),
-            token_id:"Hope.3".to_string(),
-            list_price:Asset { denom: "hope".to_string(),  amount:Uint128::new(2000000) },
-            seller:"buyer3".to_string()
+        // The remaining offering keeps its id and the new one gets a fresh id
+        let offerings = query_get_offering(deps.as_ref(), mock_env(), vec!["2".to_string(), "3".to_string()], "hope1_address".to_string(), false).unwrap();
+        assert_eq!(offerings[0].token_id, "Hope.3".to_string());
+        assert_eq!(offerings[1].token_id, "Hope.8".to_string());
+        assert_eq!(query_collection_info(deps.as_ref(), "hope1_address".to_string()).unwrap().num_offerings, 2);
+    }
+    #[test]
+    fn migrate_asset_prices() {
+        let mut deps = mock_dependencies();
+        setup_collection(deps.as_mut());
+
+        set_contract_version(deps.as_mut().storage, CONTRACT_NAME, "0.1.0").unwrap();
+        // Prices and stats stored by the previous version name cw20 tokens by symbol
+        for (id, denom) in [("1", "hope"), ("2", "ujuno")] {
+            STORED_OFFERINGS.save(deps.as_mut().storage, ("hope1_address", id), &StoredOffering {
+                token_id:format!("Hope.{}", id),
+                seller:"seller".to_string(),
+                list_price:StoredPrice { info:None, denom:Some(denom.to_string()), amount:Uint128::new(1000) },
+                dutch_auction:None,
+                expires_at:None
+            }).unwrap();
+        }
+        TVL.save(deps.as_mut().storage, ("hope1_address", "hope"), &Uint128::new(5000)).unwrap();
+        Map::<(&str, &str), SaleInfo>::new("sale").save(deps.as_mut().storage, ("hope1_address", "1"), &SaleInfo {
+            from:"seller".to_string(),
+            to:"buyer".to_string(),
+            denom:"hope".to_string(),
+            amount:Uint128::new(5000),
+            time:0,
+            nft_address:"hope1_address".to_string(),
+            token_id:"Hope.5".to_string()
+        }).unwrap();
+
+        PRICEINFO.save(deps.as_mut().storage, "hope1_address", &PriceInfo {
+            total_juno:Uint128::zero(),
+            total_hope:Uint128::new(5000)
+        }).unwrap();
+
+        // Running the migration twice converts everything once
+        migrate(deps.as_mut(), mock_env(), MigrateMsg {}).unwrap();
+        set_contract_version(deps.as_mut().storage, CONTRACT_NAME, "0.1.0").unwrap();
+        migrate(deps.as_mut(), mock_env(), MigrateMsg {}).unwrap();
+
+        let offerings = query_get_offering(deps.as_ref(), mock_env(), vec!["1".to_string(), "2".to_string()], "hope1_address".to_string(), false).unwrap();
+        assert_eq!(offerings[0].list_price.info, AssetInfo::Token{ contract_addr:"token_address".to_string() });
+        assert_eq!(offerings[1].list_price.info, AssetInfo::NativeToken{ denom:"ujuno".to_string() });
+        assert_eq!(query_get_tvl(deps.as_ref(), "hope1_address".to_string(), "token_address".to_string()).unwrap(), Uint128::new(5000));
+        assert!(!TVL.has(deps.as_ref().storage, ("hope1_address", "hope")));
+        assert_eq!(query_get_tvl(deps.as_ref(), "hope1_address".to_string(), "hope".to_string()).unwrap(), Uint128::new(5000));
+        let history = query_get_history(deps.as_ref(), "hope1_address".to_string(), vec!["1".to_string()]).unwrap();
+        assert_eq!(history[0].denom, "token_address".to_string());
+        let sales = query_sales_by_buyer(deps.as_ref(), "buyer".to_string(), None, None, false).unwrap().sales;
+        assert_eq!((sales.len(), sales[0].id.as_str()), (1, "1"));
+        assert_eq!(query_volumes(deps.as_ref(), "hope1_address".to_string()).unwrap(), vec![VolumeInfo {
+            info:AssetInfo::Token{ contract_addr:"token_address".to_string() },
+            amount:Uint128::new(5000),
+            sales:1
         }]);
+        assert_eq!(query_get_trading(deps.as_ref(), "hope1_address".to_string()).unwrap().total_hope, Uint128::new(5000));
+        let stats = query_global_stats(deps.as_ref()).unwrap();
+        assert_eq!((stats.total_sales, stats.active_listings, stats.unique_buyers, stats.unique_sellers), (1, 2, 1, 1));
+
+        // The converted cw20 listing is bought through the token contract
+        let msg = ExecuteMsg::Receive(Cw20ReceiveMsg {
+            sender:"buyer".to_string(),
+            amount:Uint128::new(1000),
+            msg:to_binary(&BuyNft { offering_id:"1".to_string(), nft_address:"hope1_address".to_string(), token_id:None }).unwrap()
+        });
+        let res = execute(deps.as_mut(), mock_env(), mock_info("token_address", &[]), msg).unwrap();
+        assert_eq!(res.messages[1].msg, CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr:"token_address".to_string(),
+            funds:vec![],
+            msg:to_binary(&Cw20ExecuteMsg::Transfer { recipient:"seller".to_string(), amount:Uint128::new(950) }).unwrap()
+        }));
+    }
+    #[test]
+    fn trading_volumes() {
+        let mut deps = mock_dependencies();
+        setup_collection(deps.as_mut());
+        let msg = ExecuteMsg::AddTokenAddress { symbol:"raw".to_string(), address:"raw_address".to_string() };
+        execute(deps.as_mut(), mock_env(), mock_info("owner", &[]), msg).unwrap();
+
+        let assets = [
+            AssetInfo::NativeToken{ denom:"ujuno".to_string() },
+            AssetInfo::NativeToken{ denom:"ibc/ATOM".to_string() },
+            AssetInfo::Token{ contract_addr:"token_address".to_string() },
+            AssetInfo::Token{ contract_addr:"raw_address".to_string() },
+        ];
+        for (i, info) in assets.iter().enumerate() {
+            let amount = Uint128::new(1000 * (i as u128 + 1));
+            let msg = ExecuteMsg::ReceiveNft(Cw721ReceiveMsg{
+                sender:"seller".to_string(),
+                token_id:format!("Hope.{}", i + 1),
+                msg:to_binary(&SellNft{ list_price:Asset { info:info.clone(), amount }, expires_at:None }).unwrap()
+            });
+            execute(deps.as_mut(), mock_env(), mock_info("hope1_address", &[]), msg).unwrap();
+            let offering_id = (i + 1).to_string();
+            match info {
+                AssetInfo::NativeToken { denom } => {
+                    let msg = ExecuteMsg::BuyNft { offering_id, nft_address:"hope1_address".to_string(), token_id:None };
+                    execute(deps.as_mut(), mock_env(), mock_info("buyer", &coins(amount.u128(), denom)), msg).unwrap();
+                }
+                AssetInfo::Token { contract_addr } => {
+                    let msg = ExecuteMsg::Receive(Cw20ReceiveMsg{
+                        sender:"buyer".to_string(),
+                        amount,
+                        msg:to_binary(&BuyNft{ offering_id, nft_address:"hope1_address".to_string(), token_id:None }).unwrap()
+                    });
+                    execute(deps.as_mut(), mock_env(), mock_info(contract_addr, &[]), msg).unwrap();
+                }
+            }
+        }
 
-        let cw20_msg= BuyNft{
-            offering_id:"2".to_string(),
-            nft_address:"hope1_address".to_string()
+        // Every asset keeps its own volume
+        let msg = QueryMsg::GetVolumes { address:"hope1_address".to_string() };
+        let volumes: Vec<VolumeInfo> = from_binary(&query(deps.as_ref(), mock_env(), msg).unwrap()).unwrap();
+        assert_eq!(volumes.len(), 4);
+        for (i, info) in assets.iter().enumerate() {
+            let volume = volumes.iter().find(|volume| volume.info == *info).unwrap();
+            assert_eq!(volume.amount, Uint128::new(1000 * (i as u128 + 1)));
+            assert_eq!(volume.sales, 1);
+        }
+
+        // The legacy totals only cover ujuno and the hope token
+        let msg = QueryMsg::GetTradingInfo { address:"hope1_address".to_string() };
+        let trading: PriceInfo = from_binary(&query(deps.as_ref(), mock_env(), msg).unwrap()).unwrap();
+        assert_eq!(trading, PriceInfo { total_juno:Uint128::new(1000), total_hope:Uint128::new(3000) });
+    }
+
+    #[test]
+    fn global_stats() {
+        let mut deps = mock_dependencies();
+        setup_collection(deps.as_mut());
+
+        for token_id in ["Hope.1", "Hope.2"] {
+            let cw721_msg = SellNft{
+                list_price:Asset { info:AssetInfo::NativeToken{ denom:"ujuno".to_string() }, amount:Uint128::new(1000) },
+                expires_at:None
+            };
+            let msg = ExecuteMsg::ReceiveNft(Cw721ReceiveMsg{
+                sender:"seller".to_string(),
+                token_id:token_id.to_string(),
+                msg:to_binary(&cw721_msg).unwrap()
+            });
+            execute(deps.as_mut(), mock_env(), mock_info("hope1_address", &[]), msg).unwrap();
+        }
+        assert_eq!(query_global_stats(deps.as_ref()).unwrap().active_listings, 2);
+
+        let msg = ExecuteMsg::BuyNft { offering_id:"1".to_string(), nft_address:"hope1_address".to_string(), token_id:None };
+        execute(deps.as_mut(), mock_env(), mock_info("buyer", &coins(1000, "ujuno")), msg).unwrap();
+        let msg = ExecuteMsg::WithdrawNft { offering_id:"2".to_string(), nft_address:"hope1_address".to_string() };
+        execute(deps.as_mut(), mock_env(), mock_info("seller", &[]), msg).unwrap();
+
+        assert_eq!(query_global_stats(deps.as_ref()).unwrap(), GlobalStatsResponse {
+            total_sales:1,
+            active_listings:0,
+            unique_buyers:1,
+            unique_sellers:1,
+            volumes:vec![VolumeInfo {
+                info:AssetInfo::NativeToken{ denom:"ujuno".to_string() },
+                amount:Uint128::new(1000),
+                sales:1
+            }]
+        });
+    }
+    #[test]
+    fn list_offerings() {
+        let mut deps = mock_dependencies();
+        setup_collection(deps.as_mut());
+        let msg = ExecuteMsg::AddCollection {
+            royalty_portion:Decimal::zero(),
+            members:vec![UserInfo{ address:"admin1".to_string(), portion:Decimal::one() }],
+            nft_address:"hope2_address".to_string(),
+            offering_id:0,
+            sale_id:0,
+            fee:None,
+            royalty_mode:None
         };
+        execute(deps.as_mut(), mock_env(), mock_info("owner", &[]), msg).unwrap();
+
+        for (nft_address, token_id) in [("hope1_address", "Hope.1"), ("hope1_address", "Hope.2"), ("hope1_address", "Hope.3"), ("hope2_address", "Hope.1")] {
+            let cw721_msg = SellNft{
+                list_price:Asset { info:AssetInfo::NativeToken{ denom:"ujuno".to_string() }, amount:Uint128::new(1000) },
+                expires_at:None
+            };
+            let msg = ExecuteMsg::ReceiveNft(Cw721ReceiveMsg{
+                sender:"seller".to_string(),
+                token_id:token_id.to_string(),
+                msg:to_binary(&cw721_msg).unwrap()
+            });
+            execute(deps.as_mut(), mock_env(), mock_info(nft_address, &[]), msg).unwrap();
+        }
 
-        let info = mock_info("token_address", &[]);
-        let msg = ExecuteMsg::Receive(Cw20ReceiveMsg{
-            sender:"test_buyer3".to_string(),
-            amount:Uint128::new(2000000),
-            msg:to_binary(&cw20_msg).unwrap()
+        let msg = QueryMsg::ListOfferings { nft_address:"hope1_address".to_string(), start_after:None, limit:Some(2) };
+        let res: OfferingsResponse = from_binary(&query(deps.as_ref(), mock_env(), msg).unwrap()).unwrap();
+        let ids: Vec<String> = res.offerings.iter().map(|offering| offering.id.clone()).collect();
+        assert_eq!(ids, vec!["1".to_string(), "2".to_string()]);
+
+        let msg = QueryMsg::ListOfferings { nft_address:"hope1_address".to_string(), start_after:Some("2".to_string()), limit:Some(2) };
+        let res: OfferingsResponse = from_binary(&query(deps.as_ref(), mock_env(), msg).unwrap()).unwrap();
+        assert_eq!(res.offerings.len(), 1);
+        assert_eq!(res.offerings[0].token_id, "Hope.3".to_string());
+
+        let msg = QueryMsg::ListAllOfferings { start_after:Some(("hope1_address".to_string(), "2".to_string())), limit:None };
+        let msg = from_binary(&to_binary(&msg).unwrap()).unwrap();
+        let res: AllOfferingsResponse = from_binary(&query(deps.as_ref(), mock_env(), msg).unwrap()).unwrap();
+        let keys: Vec<(String, String)> = res.offerings.iter()
+            .map(|offering| (offering.nft_address.clone(), offering.offering.id.clone()))
+            .collect();
+        assert_eq!(keys, vec![
+            ("hope1_address".to_string(), "3".to_string()),
+            ("hope2_address".to_string(), "1".to_string())
+        ]);
+
+        assert_eq!(query_get_ids(deps.as_ref(), "hope2_address".to_string()).unwrap(), vec!["1".to_string()]);
+
+        // Re-adding a collection keeps its id counters, new listings don't replace live ones
+        let msg = ExecuteMsg::AddCollection {
+            royalty_portion:Decimal::zero(),
+            members:vec![UserInfo{ address:"admin1".to_string(), portion:Decimal::one() }],
+            nft_address:"hope1_address".to_string(),
+            offering_id:0,
+            sale_id:0,
+            fee:None,
+            royalty_mode:None
+        };
+        execute(deps.as_mut(), mock_env(), mock_info("owner", &[]), msg).unwrap();
+        assert_eq!(query_collection_info(deps.as_ref(), "hope1_address".to_string()).unwrap().offering_id, 3);
+        let msg = ExecuteMsg::ReceiveNft(Cw721ReceiveMsg{
+            sender:"bob".to_string(),
+            token_id:"Hope.4".to_string(),
+            msg:to_binary(&SellNft{
+                list_price:Asset { info:AssetInfo::NativeToken{ denom:"ujuno".to_string() }, amount:Uint128::new(1000) },
+                expires_at:None
+            }).unwrap()
         });
-        let res = execute(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();    
-        assert_eq!(res.messages[1].msg,CosmosMsg::Wasm(WasmMsg::Execute {
-                contract_addr: "token_address".to_string(),
-                funds: vec![],
-                msg: to_binary(&Cw20ExecuteMsg::Transfer { 
-                    recipient: "buyer3".to_string(), 
-                    amount:Uint128::new(1900000),
-                 }).unwrap()
-         }));
+        execute(deps.as_mut(), mock_env(), mock_info("hope1_address", &[]), msg).unwrap();
+        assert_eq!(query_offering_by_token(deps.as_ref(), "hope1_address".to_string(), "Hope.4".to_string()).unwrap().unwrap().id, "4");
+        assert_eq!(query_offering_by_token(deps.as_ref(), "hope1_address".to_string(), "Hope.1".to_string()).unwrap().unwrap().id, "1");
+
+        // Ids are ordered as numbers, "10" comes after "9"
+        for token_id in 5..=10 {
+            let msg = ExecuteMsg::ReceiveNft(Cw721ReceiveMsg{
+                sender:"bob".to_string(),
+                token_id:format!("Hope.{}", token_id),
+                msg:to_binary(&SellNft{
+                    list_price:Asset { info:AssetInfo::NativeToken{ denom:"ujuno".to_string() }, amount:Uint128::new(1000) },
+                    expires_at:None
+                }).unwrap()
+            });
+            execute(deps.as_mut(), mock_env(), mock_info("hope1_address", &[]), msg).unwrap();
+        }
+        let msg = QueryMsg::ListOfferings { nft_address:"hope1_address".to_string(), start_after:Some("8".to_string()), limit:None };
+        let res: OfferingsResponse = from_binary(&query(deps.as_ref(), mock_env(), msg).unwrap()).unwrap();
+        let ids: Vec<String> = res.offerings.iter().map(|offering| offering.id.clone()).collect();
+        assert_eq!(ids, vec!["9".to_string(), "10".to_string()]);
+        assert_eq!(query_get_ids(deps.as_ref(), "hope1_address".to_string()).unwrap().last().unwrap(), "10");
+    }
+    #[test]
+    fn offering_indexes() {
+        let mut deps = mock_dependencies();
+        setup_collection(deps.as_mut());
+
+        for (seller, token_id) in [("seller1", "Hope.1"), ("seller2", "Hope.2"), ("seller1", "Hope.3"), ("seller1", "Hope.4")] {
+            let cw721_msg = SellNft{
+                list_price:Asset { info:AssetInfo::NativeToken{ denom:"ujuno".to_string() }, amount:Uint128::new(1000) },
+                expires_at:None
+            };
+            let msg = ExecuteMsg::ReceiveNft(Cw721ReceiveMsg{
+                sender:seller.to_string(),
+                token_id:token_id.to_string(),
+                msg:to_binary(&cw721_msg).unwrap()
+            });
+            execute(deps.as_mut(), mock_env(), mock_info("hope1_address", &[]), msg).unwrap();
+        }
 
-         assert_eq!(res.messages[2].msg,CosmosMsg::Wasm(WasmMsg::Execute {
-                contract_addr: "token_address".to_string(),
-                funds: vec![],
-                msg: to_binary(&Cw20ExecuteMsg::Transfer { 
-                    recipient: "admin1".to_string(), 
-                    amount:Uint128::new(30000),
-                 }).unwrap()
-         }));
+        let res = query_offerings_by_seller(deps.as_ref(), "seller1".to_string(), None, Some(2)).unwrap();
+        let tokens: Vec<String> = res.offerings.iter().map(|offering| offering.offering.token_id.clone()).collect();
+        assert_eq!(tokens, vec!["Hope.1".to_string(), "Hope.3".to_string()]);
+        let res = query_offerings_by_seller(deps.as_ref(), "seller1".to_string(), Some(("hope1_address".to_string(), "3".to_string())), None).unwrap();
+        assert_eq!(res.offerings.len(), 1);
+        assert_eq!(res.offerings[0].offering.token_id, "Hope.4".to_string());
+
+        let offering = query_offering_by_token(deps.as_ref(), "hope1_address".to_string(), "Hope.2".to_string()).unwrap().unwrap();
+        assert_eq!((offering.id, offering.seller), ("2".to_string(), "seller2".to_string()));
+
+        // Sold tokens drop out of both indexes
+        let msg = ExecuteMsg::BuyNft { offering_id:"2".to_string(), nft_address:"hope1_address".to_string(), token_id:None };
+        execute(deps.as_mut(), mock_env(), mock_info("buyer", &coins(1000, "ujuno")), msg).unwrap();
+        assert_eq!(query_offering_by_token(deps.as_ref(), "hope1_address".to_string(), "Hope.2".to_string()).unwrap(), None);
+        assert!(query_offerings_by_seller(deps.as_ref(), "seller2".to_string(), None, None).unwrap().offerings.is_empty());
+    }
+    #[test]
+    fn offerings_by_price() {
+        let mut deps = mock_dependencies();
+        setup_collection(deps.as_mut());
+
+        let ujuno = AssetInfo::NativeToken{ denom:"ujuno".to_string() };
+        let hope = AssetInfo::Token{ contract_addr:"token_address".to_string() };
+        for (token_id, info, amount) in [("Hope.1", &ujuno, 3000), ("Hope.2", &ujuno, 1000), ("Hope.3", &ujuno, 2000), ("Hope.4", &hope, 500)] {
+            let cw721_msg = SellNft{
+                list_price:Asset { info:info.clone(), amount:Uint128::new(amount) },
+                expires_at:None
+            };
+            let msg = ExecuteMsg::ReceiveNft(Cw721ReceiveMsg{
+                sender:"seller".to_string(),
+                token_id:token_id.to_string(),
+                msg:to_binary(&cw721_msg).unwrap()
+            });
+            execute(deps.as_mut(), mock_env(), mock_info("hope1_address", &[]), msg).unwrap();
+        }
+        // Listed apart from the fixed price offerings, by its floor price of 2000
+        let start_time = mock_env().block.time.seconds();
+        let hook = NftHookMsg::StartDutchAuction(StartDutchAuction {
+            start_price: Asset { info:ujuno.clone(), amount:Uint128::new(10000) },
+            floor_price: Uint128::new(2000),
+            start_time,
+            end_time: start_time + 1000,
+            step: None
+        });
+        let msg = ExecuteMsg::ReceiveNft(Cw721ReceiveMsg{
+            sender:"seller".to_string(),
+            token_id:"Hope.5".to_string(),
+            msg:to_binary(&hook).unwrap()
+        });
+        execute(deps.as_mut(), mock_env(), mock_info("hope1_address", &[]), msg).unwrap();
+
+        let ids = |res: OfferingsResponse| res.offerings.into_iter().map(|offering| offering.id).collect::<Vec<String>>();
+        let res = query_offerings_by_price(deps.as_ref(), "hope1_address".to_string(), ujuno.clone(), None, None, false).unwrap();
+        assert_eq!(ids(res), vec!["2", "3", "1"]);
+        let res = query_offerings_by_price(deps.as_ref(), "hope1_address".to_string(), ujuno.clone(), Some((Uint128::new(1000), "2".to_string())), Some(1), false).unwrap();
+        assert_eq!(ids(res), vec!["3"]);
+        let res = query_offerings_by_price(deps.as_ref(), "hope1_address".to_string(), ujuno.clone(), None, Some(2), true).unwrap();
+        assert_eq!(ids(res), vec!["1", "3"]);
+        let res = query_offerings_by_price(deps.as_ref(), "hope1_address".to_string(), ujuno.clone(), Some((Uint128::new(2000), "3".to_string())), None, true).unwrap();
+        assert_eq!(ids(res), vec!["2"]);
+        let msg = QueryMsg::DutchAuctions { nft_address:"hope1_address".to_string(), asset:ujuno.clone(), start_after:None, limit:None, descending:None };
+        assert_eq!(ids(from_binary(&query(deps.as_ref(), mock_env(), msg).unwrap()).unwrap()), vec!["5"]);
+        let res = query_offerings_by_price(deps.as_ref(), "hope1_address".to_string(), hope.clone(), None, None, false).unwrap();
+        assert_eq!(ids(res), vec!["4"]);
+
+        let floor = query_floor_price(deps.as_ref(), mock_env(), "hope1_address".to_string(), ujuno.clone()).unwrap().unwrap();
+        assert_eq!((floor.offering.id, floor.price.amount), ("2".to_string(), Uint128::new(1000)));
+        let floor = query_floor_price(deps.as_ref(), mock_env(), "hope1_address".to_string(), hope).unwrap().unwrap();
+        assert_eq!((floor.offering.id, floor.price.amount), ("4".to_string(), Uint128::new(500)));
+
+        // Repricing moves the offering in the index
+        let msg = ExecuteMsg::UpdateOffering { offering_id:"1".to_string(), nft_address:"hope1_address".to_string(), list_price:Asset { info:ujuno.clone(), amount:Uint128::new(1500) } };
+        execute(deps.as_mut(), mock_env(), mock_info("seller", &[]), msg).unwrap();
+        let msg = ExecuteMsg::BuyNft { offering_id:"2".to_string(), nft_address:"hope1_address".to_string(), token_id:None };
+        execute(deps.as_mut(), mock_env(), mock_info("buyer", &coins(1000, "ujuno")), msg).unwrap();
+        let res = query_offerings_by_price(deps.as_ref(), "hope1_address".to_string(), ujuno.clone(), None, None, false).unwrap();
+        assert_eq!(ids(res), vec!["1", "3"]);
+
+        // The Dutch auction becomes the floor once its price falls below the fixed listings
+        let msg = ExecuteMsg::BuyNft { offering_id:"1".to_string(), nft_address:"hope1_address".to_string(), token_id:None };
+        execute(deps.as_mut(), mock_env(), mock_info("buyer", &coins(1500, "ujuno")), msg).unwrap();
+        let floor = query_floor_price(deps.as_ref(), mock_env(), "hope1_address".to_string(), ujuno.clone()).unwrap().unwrap();
+        assert_eq!(floor.offering.id, "3".to_string());
+        let msg = ExecuteMsg::BuyNft { offering_id:"3".to_string(), nft_address:"hope1_address".to_string(), token_id:None };
+        execute(deps.as_mut(), mock_env(), mock_info("buyer", &coins(2000, "ujuno")), msg).unwrap();
+        let mut env = mock_env();
+        env.block.time = env.block.time.plus_seconds(500);
+        let floor = query_floor_price(deps.as_ref(), env, "hope1_address".to_string(), ujuno).unwrap().unwrap();
+        assert_eq!((floor.offering.id, floor.price.amount), ("5".to_string(), Uint128::new(6000)));
+    }
+    #[test]
+    fn sale_history_queries() {
+        let mut deps = mock_dependencies();
+        setup_collection(deps.as_mut());
+
+        let list = |deps: DepsMut, seller: &str, token_id: &str| {
+            let cw721_msg = SellNft{
+                list_price:Asset { info:AssetInfo::NativeToken{ denom:"ujuno".to_string() }, amount:Uint128::new(1000) },
+                expires_at:None
+            };
+            let msg = ExecuteMsg::ReceiveNft(Cw721ReceiveMsg{
+                sender:seller.to_string(),
+                token_id:token_id.to_string(),
+                msg:to_binary(&cw721_msg).unwrap()
+            });
+            execute(deps, mock_env(), mock_info("hope1_address", &[]), msg).unwrap();
+        };
+        let buy = |deps: DepsMut, buyer: &str, offering_id: &str, seconds: u64| {
+            let mut env = mock_env();
+            env.block.time = env.block.time.plus_seconds(seconds);
+            let msg = ExecuteMsg::BuyNft { offering_id:offering_id.to_string(), nft_address:"hope1_address".to_string(), token_id:None };
+            execute(deps, env, mock_info(buyer, &coins(1000, "ujuno")), msg).unwrap();
+        };
+        list(deps.as_mut(), "seller", "Hope.1");
+        list(deps.as_mut(), "seller", "Hope.2");
+        buy(deps.as_mut(), "buyer1", "1", 0);
+        buy(deps.as_mut(), "buyer2", "2", 10);
+        // Hope.1 changes hands again
+        list(deps.as_mut(), "buyer1", "Hope.1");
+        buy(deps.as_mut(), "buyer2", "3", 20);
+
+        let ids = |res: SalesResponse| res.sales.into_iter().map(|sale| sale.id).collect::<Vec<String>>();
+        assert_eq!(ids(query_list_sales(deps.as_ref(), "hope1_address".to_string(), None, None, false).unwrap()), vec!["1", "2", "3"]);
+        assert_eq!(ids(query_list_sales(deps.as_ref(), "hope1_address".to_string(), None, Some(2), true).unwrap()), vec!["3", "2"]);
+        assert_eq!(ids(query_list_sales(deps.as_ref(), "hope1_address".to_string(), Some("2".to_string()), None, false).unwrap()), vec!["3"]);
+        assert_eq!(ids(query_list_all_sales(deps.as_ref(), None, Some(1), true).unwrap()), vec!["3"]);
+
+        assert_eq!(ids(query_sales_by_buyer(deps.as_ref(), "buyer2".to_string(), None, None, false).unwrap()), vec!["2", "3"]);
+        let cursor = Some(("hope1_address".to_string(), "3".to_string()));
+        assert_eq!(ids(query_sales_by_buyer(deps.as_ref(), "buyer2".to_string(), cursor, None, true).unwrap()), vec!["2"]);
+        assert_eq!(ids(query_sales_by_seller(deps.as_ref(), "seller".to_string(), None, None, false).unwrap()), vec!["1", "2"]);
+        assert_eq!(ids(query_sales_by_seller(deps.as_ref(), "buyer1".to_string(), None, None, false).unwrap()), vec!["3"]);
+
+        // Provenance of a token
+        let sales = query_sales_by_token(deps.as_ref(), "hope1_address".to_string(), "Hope.1".to_string(), None, None, false).unwrap().sales;
+        let owners: Vec<(&str, &str)> = sales.iter().map(|sale| (sale.sale.from.as_str(), sale.sale.to.as_str())).collect();
+        assert_eq!(owners, vec![("seller", "buyer1"), ("buyer1", "buyer2")]);
+
+        let err = query_list_sales(deps.as_ref(), "hope1_address".to_string(), Some("9".to_string()), None, false).unwrap_err();
+        assert!(matches!(err, StdError::NotFound { .. }));
+    }
+    #[test]
+    fn import_sale_history() {
+        let mut deps = mock_dependencies();
+        setup_collection(deps.as_mut());
 
-          assert_eq!(res.messages[3].msg,CosmosMsg::Wasm(WasmMsg::Execute {
-                contract_addr: "token_address".to_string(),
-                funds: vec![],
-                msg: to_binary(&Cw20ExecuteMsg::Transfer { 
-                    recipient: "admin2".to_string(), 
-                    amount:Uint128::new(70000),
-                 }).unwrap()
-         }));
+        let sale = |token_id: &str, nft_address: &str| SaleInfo {
+            from:"seller".to_string(),
+            to:"buyer".to_string(),
+            denom:"ujuno".to_string(),
+            amount:Uint128::new(1000),
+            time:0,
+            nft_address:nft_address.to_string(),
+            token_id:token_id.to_string()
+        };
+        let msg = ExecuteMsg::SetSaleHistory { address:"hope1_address".to_string(), history:vec![sale("Hope.1", "hope1_address"), sale("Hope.2", "hope1_address")] };
+        let err = execute(deps.as_mut(), mock_env(), mock_info("buyer", &[]), msg.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::MissingRole { role: Role::Migrator }));
+        let res = execute(deps.as_mut(), mock_env(), mock_info("owner", &[]), msg).unwrap();
+        assert!(res.attributes.contains(&attr("count", "2")));
+
+        // A second batch is appended instead of overwriting the first
+        let msg = ExecuteMsg::SetSaleHistory { address:"hope1_address".to_string(), history:vec![sale("Hope.3", "hope1_address")] };
+        execute(deps.as_mut(), mock_env(), mock_info("owner", &[]), msg).unwrap();
+        let msg = ExecuteMsg::SetSaleHistory { address:"hope1_address".to_string(), history:vec![sale("Hope.4", "hope2_address")] };
+        let err = execute(deps.as_mut(), mock_env(), mock_info("owner", &[]), msg).unwrap_err();
+        assert!(matches!(err, ContractError::WrongCollection {}));
+
+        // Live sales continue after the imported ids
+        let cw721_msg = SellNft{
+            list_price:Asset { info:AssetInfo::NativeToken{ denom:"ujuno".to_string() }, amount:Uint128::new(1000) },
+            expires_at:None
+        };
+        let msg = ExecuteMsg::ReceiveNft(Cw721ReceiveMsg{
+            sender:"seller".to_string(),
+            token_id:"Hope.5".to_string(),
+            msg:to_binary(&cw721_msg).unwrap()
+        });
+        execute(deps.as_mut(), mock_env(), mock_info("hope1_address", &[]), msg).unwrap();
+        let msg = ExecuteMsg::BuyNft { offering_id:"1".to_string(), nft_address:"hope1_address".to_string(), token_id:None };
+        execute(deps.as_mut(), mock_env(), mock_info("buyer", &coins(1000, "ujuno")), msg).unwrap();
+
+        let ids: Vec<String> = (1..=4).map(|id| id.to_string()).collect();
+        let history = query_get_history(deps.as_ref(), "hope1_address".to_string(), ids).unwrap();
+        let tokens: Vec<&str> = history.iter().map(|sale| sale.token_id.as_str()).collect();
+        assert_eq!(tokens, vec!["Hope.1", "Hope.2", "Hope.3", "Hope.5"]);
+        assert_eq!(query_collection_info(deps.as_ref(), "hope1_address".to_string()).unwrap().sale_id, 4);
+    }
+    #[test]
+    fn event_attributes() {
+        let mut deps = mock_dependencies();
+        setup_collection(deps.as_mut());
+
+        for token_id in ["Hope.1", "Hope.2"] {
+            let cw721_msg = SellNft{
+                list_price:Asset { info:AssetInfo::NativeToken{ denom:"ujuno".to_string() }, amount:Uint128::new(1000) },
+                expires_at:None
+            };
+            let msg = ExecuteMsg::ReceiveNft(Cw721ReceiveMsg{
+                sender:"seller".to_string(),
+                token_id:token_id.to_string(),
+                msg:to_binary(&cw721_msg).unwrap()
+            });
+            let res = execute(deps.as_mut(), mock_env(), mock_info("hope1_address", &[]), msg).unwrap();
+            assert_eq!(res.attributes[0], attr("action", "sell_nft"));
+            assert!(res.attributes.contains(&attr("token_id", token_id)));
+            assert!(res.attributes.contains(&attr("price", "1000ujuno")));
+        }
 
+        let msg = ExecuteMsg::BuyNft { offering_id:"1".to_string(), nft_address:"hope1_address".to_string(), token_id:None };
+        let res = execute(deps.as_mut(), mock_env(), mock_info("buyer", &coins(1000, "ujuno")), msg).unwrap();
+        let expected = vec![
+            attr("collection", "hope1_address"),
+            attr("token_id", "Hope.1"),
+            attr("sale_id", "1"),
+            attr("seller", "seller"),
+            attr("buyer", "buyer"),
+            attr("price", "1000ujuno"),
+            attr("royalty", "50"),
+            attr("fee_rate", "0"),
+            attr("fee", "0"),
+            attr("offering_id", "1"),
+        ];
+        assert_eq!(res.attributes[0], attr("action", "buy_nft"));
+        assert_eq!(res.attributes[1..], expected[..]);
+        // Indexers can also follow the typed event
+        assert_eq!(res.events, vec![Event::new("buy_nft").add_attributes(expected)]);
+
+        let msg = ExecuteMsg::WithdrawNft { offering_id:"2".to_string(), nft_address:"hope1_address".to_string() };
+        let res = execute(deps.as_mut(), mock_env(), mock_info("seller", &[]), msg).unwrap();
+        assert_eq!(res.events[0].ty, "withdraw_nft".to_string());
+        assert_eq!(res.attributes, vec![
+            attr("action", "withdraw_nft"),
+            attr("collection", "hope1_address"),
+            attr("token_id", "Hope.2"),
+            attr("offering_id", "2"),
+            attr("seller", "seller"),
+        ]);
+    }
+    #[test]
+    fn migrate_versions() {
+        let mut deps = mock_dependencies();
+        setup_collection(deps.as_mut());
+
+        // Nothing to run when the version is unchanged
+        let res = migrate(deps.as_mut(), mock_env(), MigrateMsg {}).unwrap();
+        assert!(res.attributes.contains(&attr("migrations", "")));
+
+        set_contract_version(deps.as_mut().storage, CONTRACT_NAME, "9.0.0").unwrap();
+        let err = migrate(deps.as_mut(), mock_env(), MigrateMsg {}).unwrap_err();
+        assert!(matches!(err, ContractError::InvalidVersion { .. }));
+        set_contract_version(deps.as_mut().storage, "other_contract", "0.1.0").unwrap();
+        let err = migrate(deps.as_mut(), mock_env(), MigrateMsg {}).unwrap_err();
+        assert!(matches!(err, ContractError::WrongContract { .. }));
+
+        // The first release had no fee collector, nor auction indexes and deposit records
+        set_contract_version(deps.as_mut().storage, CONTRACT_NAME, "0.1.0").unwrap();
+        CONFIG.update(deps.as_mut().storage, |mut state| -> StdResult<_> {
+            state.fee_collector = String::new();
+            Ok(state)
+        }).unwrap();
+        let stored_auctions: Map<(&str,&str), Auction> = Map::new("auctions");
+        stored_auctions.save(deps.as_mut().storage, ("hope1_address", "1"), &Auction {
+            nft_address:String::new(),
+            token_id:"Hope.1".to_string(),
+            seller:"seller".to_string(),
+            reserve_price:Asset { info:AssetInfo::NativeToken{ denom:"ujuno".to_string() }, amount:Uint128::new(1000) },
+            min_increment:Uint128::new(100),
+            end_time:mock_env().block.time.seconds() + 3600,
+            highest_bid:None
+        }).unwrap();
+        assert!(!token_in_auction(deps.as_ref().storage, "hope1_address", "Hope.1").unwrap());
+        // Hope.6 went back into escrow after its last sale and lost its offering
+        let stored_sales: Map<(&str,&str), SaleInfo> = Map::new("sale");
+        for (id, from, to, time) in [("1", "seller", "buyer1", 0), ("2", "buyer1", "buyer2", 10)] {
+            stored_sales.save(deps.as_mut().storage, ("hope1_address", id), &SaleInfo {
+                from:from.to_string(),
+                to:to.to_string(),
+                denom:"ujuno".to_string(),
+                amount:Uint128::new(1000),
+                time,
+                nft_address:"hope1_address".to_string(),
+                token_id:"Hope.6".to_string()
+            }).unwrap();
+        }
+        let res = migrate(deps.as_mut(), mock_env(), MigrateMsg {}).unwrap();
+        assert!(res.attributes.contains(&attr("migrations", "0.2.0")));
+        assert_eq!(query_fee_info(deps.as_ref()).unwrap().fee_collector, "owner".to_string());
+        assert!(token_in_auction(deps.as_ref().storage, "hope1_address", "Hope.1").unwrap());
+        assert_eq!(DEPOSITS.load(deps.as_ref().storage, ("hope1_address", "Hope.1")).unwrap(), "seller".to_string());
+        assert_eq!(get_contract_version(deps.as_ref().storage).unwrap().version, CONTRACT_VERSION.to_string());
+
+        // The orphan goes back to its latest buyer, tokens never sold here wait for a transfer proposal
+        let claim = |token_id: &str| ExecuteMsg::ClaimOrphanedNft { nft_address:"hope1_address".to_string(), token_id:token_id.to_string() };
+        let err = execute(deps.as_mut(), mock_env(), mock_info("buyer1", &[]), claim("Hope.6")).unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized {}));
+        let res = execute(deps.as_mut(), mock_env(), mock_info("buyer2", &[]), claim("Hope.6")).unwrap();
+        assert_eq!(res.messages[0].msg, CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr:"hope1_address".to_string(),
+            funds:vec![],
+            msg:to_binary(&Cw721ExecuteMsg::TransferNft { recipient:"buyer2".to_string(), token_id:"Hope.6".to_string() }).unwrap()
+        }));
+        assert!(!DEPOSITS.has(deps.as_ref().storage, ("hope1_address", "Hope.7")));
+        let err = execute(deps.as_mut(), mock_env(), mock_info("seller", &[]), claim("Hope.7")).unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized {}));
+    }
+    #[test]
+    fn ownership_transfer() {
+        let mut deps = mock_dependencies();
+        setup_collection(deps.as_mut());
+        let now = mock_env().block.time.seconds();
+
+        let msg = ExecuteMsg::ProposeOwner { address:"new_owner".to_string(), expires:Some(now + 100) };
+        let err = execute(deps.as_mut(), mock_env(), mock_info("new_owner", &[]), msg.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized {}));
+        execute(deps.as_mut(), mock_env(), mock_info("owner", &[]), msg).unwrap();
+        assert_eq!(query_pending_owner(deps.as_ref()).unwrap(), Some(PendingOwner { owner:"new_owner".to_string(), expires:Some(now + 100) }));
+
+        // Only the proposed owner accepts, and only before the proposal expires
+        let err = execute(deps.as_mut(), mock_env(), mock_info("someone", &[]), ExecuteMsg::AcceptOwnership {}).unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized {}));
+        let mut env = mock_env();
+        env.block.time = env.block.time.plus_seconds(100);
+        let err = execute(deps.as_mut(), env, mock_info("new_owner", &[]), ExecuteMsg::AcceptOwnership {}).unwrap_err();
+        assert!(matches!(err, ContractError::ProposalExpired {}));
+
+        execute(deps.as_mut(), mock_env(), mock_info("owner", &[]), ExecuteMsg::CancelProposal {}).unwrap();
+        assert_eq!(query_pending_owner(deps.as_ref()).unwrap(), None);
+        let err = execute(deps.as_mut(), mock_env(), mock_info("new_owner", &[]), ExecuteMsg::AcceptOwnership {}).unwrap_err();
+        assert!(matches!(err, ContractError::NoPendingOwner {}));
+
+        let msg = ExecuteMsg::ProposeOwner { address:"new_owner".to_string(), expires:None };
+        execute(deps.as_mut(), mock_env(), mock_info("owner", &[]), msg).unwrap();
+        execute(deps.as_mut(), mock_env(), mock_info("new_owner", &[]), ExecuteMsg::AcceptOwnership {}).unwrap();
+        assert_eq!(query_state_info(deps.as_ref()).unwrap().owner, "new_owner".to_string());
+        assert_eq!(query_pending_owner(deps.as_ref()).unwrap(), None);
+
+        // Nobody can act as owner after renouncing
+        let err = execute(deps.as_mut(), mock_env(), mock_info("owner", &[]), ExecuteMsg::RenounceOwnership {}).unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized {}));
+        let grant = ExecuteMsg::GrantRole { role:Role::Curator, address:"ops".to_string() };
+        execute(deps.as_mut(), mock_env(), mock_info("new_owner", &[]), grant).unwrap();
+        execute(deps.as_mut(), mock_env(), mock_info("new_owner", &[]), ExecuteMsg::RenounceOwnership {}).unwrap();
+        let msg = ExecuteMsg::AddTokenAddress { symbol:"raw".to_string(), address:"raw_address".to_string() };
+        let err = execute(deps.as_mut(), mock_env(), mock_info("new_owner", &[]), msg.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::MissingRole { role: Role::Curator }));
+        // Granted roles go with the owner
+        assert!(query_roles(deps.as_ref(), "ops".to_string()).unwrap().is_empty());
+        let err = execute(deps.as_mut(), mock_env(), mock_info("ops", &[]), msg).unwrap_err();
+        assert!(matches!(err, ContractError::MissingRole { role: Role::Curator }));
+    }
+    #[test]
+    fn roles() {
+        let mut deps = mock_dependencies();
+        setup_collection(deps.as_mut());
 
-        let offerings = query_get_offering(deps.as_ref(),vec!["1".to_string(),"2".to_string()],"hope1_address".to_string()).unwrap();
-        assert_eq!(offerings,vec![QueryOfferingsResult{
-            id:"1".to_string(),
-            token_id:"Hope.2".to_string(),
-            list_price:Asset { denom: "ujuno".to_string(),  amount:Uint128::new(2000000) },
-            seller:"buyer2".to_string()
-        }]);
+        let add_collection = ExecuteMsg::AddCollection {
+            royalty_portion:Decimal::zero(),
+            members:vec![UserInfo{ address:"admin1".to_string(), portion:Decimal::one() }],
+            nft_address:"hope2_address".to_string(),
+            offering_id:0,
+            sale_id:0,
+            fee:None,
+            royalty_mode:None
+        };
+        let err = execute(deps.as_mut(), mock_env(), mock_info("ops", &[]), add_collection.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::MissingRole { role: Role::Curator }));
+
+        // Only the owner hands out roles
+        let grant = ExecuteMsg::GrantRole { role:Role::Curator, address:"ops".to_string() };
+        let err = execute(deps.as_mut(), mock_env(), mock_info("ops", &[]), grant.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized {}));
+        execute(deps.as_mut(), mock_env(), mock_info("owner", &[]), grant).unwrap();
+        assert_eq!(query_roles(deps.as_ref(), "ops".to_string()).unwrap(), vec![Role::Curator]);
+        assert_eq!(query_role_members(deps.as_ref(), Role::Curator).unwrap(), vec!["ops".to_string()]);
+
+        execute(deps.as_mut(), mock_env(), mock_info("ops", &[]), add_collection.clone()).unwrap();
+        let msg = ExecuteMsg::SetTvl { address:"hope2_address".to_string(), tvl:vec![] };
+        let err = execute(deps.as_mut(), mock_env(), mock_info("ops", &[]), msg).unwrap_err();
+        assert!(matches!(err, ContractError::MissingRole { role: Role::Migrator }));
+        let msg = ExecuteMsg::UpdateConfig { fee:Some(Decimal::percent(1)), fee_collector:None, fee_tiers:None };
+        let err = execute(deps.as_mut(), mock_env(), mock_info("ops", &[]), msg).unwrap_err();
+        assert!(matches!(err, ContractError::MissingRole { role: Role::Treasury }));
+
+        let msg = ExecuteMsg::RevokeRole { role:Role::Curator, address:"ops".to_string() };
+        execute(deps.as_mut(), mock_env(), mock_info("owner", &[]), msg).unwrap();
+        assert!(query_roles(deps.as_ref(), "ops".to_string()).unwrap().is_empty());
+        let err = execute(deps.as_mut(), mock_env(), mock_info("ops", &[]), add_collection).unwrap_err();
+        assert!(matches!(err, ContractError::MissingRole { role: Role::Curator }));
+    }
+    #[test]
+    fn pause() {
+        let mut deps = mock_dependencies();
+        setup_collection(deps.as_mut());
+
+        let sell = |token_id: &str, info: AssetInfo| {
+            let cw721_msg = SellNft{ list_price:Asset { info, amount:Uint128::new(1000) }, expires_at:None };
+            ExecuteMsg::ReceiveNft(Cw721ReceiveMsg{
+                sender:"seller".to_string(),
+                token_id:token_id.to_string(),
+                msg:to_binary(&cw721_msg).unwrap()
+            })
+        };
+        let ujuno = AssetInfo::NativeToken{ denom:"ujuno".to_string() };
+        execute(deps.as_mut(), mock_env(), mock_info("hope1_address", &[]), sell("Hope.1", ujuno.clone())).unwrap();
+        execute(deps.as_mut(), mock_env(), mock_info("hope1_address", &[]), sell("Hope.2", AssetInfo::Token{ contract_addr:"token_address".to_string() })).unwrap();
+        // Auction 1 gets a bid, auction 2 none
+        for token_id in ["Hope.4", "Hope.5"] {
+            let hook = NftHookMsg::StartAuction(StartAuction {
+                reserve_price: Asset { info:ujuno.clone(), amount:Uint128::new(1000) },
+                min_increment: Uint128::new(100),
+                end_time: mock_env().block.time.seconds() + 1000
+            });
+            let msg = ExecuteMsg::ReceiveNft(Cw721ReceiveMsg{
+                sender:"seller".to_string(),
+                token_id:token_id.to_string(),
+                msg:to_binary(&hook).unwrap()
+            });
+            execute(deps.as_mut(), mock_env(), mock_info("hope1_address", &[]), msg).unwrap();
+        }
+        let msg = ExecuteMsg::PlaceBid { nft_address:"hope1_address".to_string(), auction_id:"1".to_string() };
+        execute(deps.as_mut(), mock_env(), mock_info("bidder", &coins(1000, "ujuno")), msg).unwrap();
+
+        let pause = ExecuteMsg::Pause { nft_address:Some("hope1_address".to_string()) };
+        let err = execute(deps.as_mut(), mock_env(), mock_info("operator", &[]), pause.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::MissingRole { role: Role::Operator }));
+        let msg = ExecuteMsg::GrantRole { role:Role::Operator, address:"operator".to_string() };
+        execute(deps.as_mut(), mock_env(), mock_info("owner", &[]), msg).unwrap();
+        execute(deps.as_mut(), mock_env(), mock_info("operator", &[]), pause).unwrap();
+        assert_eq!(query_pause_status(deps.as_ref()).unwrap(), PauseStatusResponse { global:false, collections:vec!["hope1_address".to_string()] });
+
+        // Listing and buying stop, natively and through cw20
+        let err = execute(deps.as_mut(), mock_env(), mock_info("hope1_address", &[]), sell("Hope.3", ujuno.clone())).unwrap_err();
+        assert!(matches!(err, ContractError::Paused {}));
+        let buy = ExecuteMsg::BuyNft { offering_id:"1".to_string(), nft_address:"hope1_address".to_string(), token_id:None };
+        let err = execute(deps.as_mut(), mock_env(), mock_info("buyer", &coins(1000, "ujuno")), buy.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::Paused {}));
+        let msg = ExecuteMsg::Receive(Cw20ReceiveMsg {
+            sender:"buyer".to_string(),
+            amount:Uint128::new(1000),
+            msg:to_binary(&BuyNft { offering_id:"2".to_string(), nft_address:"hope1_address".to_string(), token_id:None }).unwrap()
+        });
+        let err = execute(deps.as_mut(), mock_env(), mock_info("token_address", &[]), msg).unwrap_err();
+        assert!(matches!(err, ContractError::Paused {}));
+
+        // Sellers can still take their nfts back, ended auctions with bids wait for the unpause
+        let msg = ExecuteMsg::WithdrawNft { offering_id:"2".to_string(), nft_address:"hope1_address".to_string() };
+        execute(deps.as_mut(), mock_env(), mock_info("seller", &[]), msg).unwrap();
+        let mut env = mock_env();
+        env.block.time = env.block.time.plus_seconds(1000);
+        let msg = ExecuteMsg::SettleAuction { nft_address:"hope1_address".to_string(), auction_id:"2".to_string() };
+        let res = execute(deps.as_mut(), env.clone(), mock_info("anyone", &[]), msg).unwrap();
+        assert_eq!(res.messages[0].msg, CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr:"hope1_address".to_string(),
+            funds:vec![],
+            msg:to_binary(&Cw721ExecuteMsg::TransferNft { recipient:"seller".to_string(), token_id:"Hope.5".to_string() }).unwrap()
+        }));
+        let settle = ExecuteMsg::SettleAuction { nft_address:"hope1_address".to_string(), auction_id:"1".to_string() };
+        let err = execute(deps.as_mut(), env.clone(), mock_info("anyone", &[]), settle.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::Paused {}));
+
+        let msg = ExecuteMsg::Unpause { nft_address:Some("hope1_address".to_string()) };
+        execute(deps.as_mut(), mock_env(), mock_info("operator", &[]), msg).unwrap();
+        execute(deps.as_mut(), mock_env(), mock_info("owner", &[]), ExecuteMsg::Pause { nft_address:None }).unwrap();
+        assert_eq!(query_pause_status(deps.as_ref()).unwrap(), PauseStatusResponse { global:true, collections:vec![] });
+        let err = execute(deps.as_mut(), mock_env(), mock_info("buyer", &coins(1000, "ujuno")), buy.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::Paused {}));
+
+        execute(deps.as_mut(), mock_env(), mock_info("operator", &[]), ExecuteMsg::Unpause { nft_address:None }).unwrap();
+        execute(deps.as_mut(), mock_env(), mock_info("buyer", &coins(1000, "ujuno")), buy).unwrap();
+        execute(deps.as_mut(), env, mock_info("anyone", &[]), settle).unwrap();
+    }
+    #[test]
+    fn nft_transfers() {
+        let mut deps = mock_dependencies();
+        setup_collection(deps.as_mut());
 
         let cw721_msg = SellNft{
-            list_price:Asset{
-                denom:"raw".to_string(),
-                amount:Uint128::new(2000000)
-            }
+            list_price:Asset { info:AssetInfo::NativeToken{ denom:"ujuno".to_string() }, amount:Uint128::new(1000) },
+            expires_at:None
         };
-
-        let info = mock_info("hope1_address", &[]);
         let msg = ExecuteMsg::ReceiveNft(Cw721ReceiveMsg{
-            sender:"owner1".to_string(),
+            sender:"seller".to_string(),
             token_id:"Hope.1".to_string(),
             msg:to_binary(&cw721_msg).unwrap()
         });
-        execute(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
-
-        let cw20_msg= BuyNft{
-            offering_id:"2".to_string(),
-            nft_address:"hope1_address".to_string()
-        };
-
-        let info = mock_info("raw_address", &[]);
-        let msg = ExecuteMsg::Receive(Cw20ReceiveMsg{
-            sender:"test_buyer4".to_string(),
-            amount:Uint128::new(2000000),
-            msg:to_binary(&cw20_msg).unwrap()
+        execute(deps.as_mut(), mock_env(), mock_info("hope1_address", &[]), msg).unwrap();
+        let hook = NftHookMsg::StartAuction(StartAuction {
+            reserve_price: Asset { info:AssetInfo::NativeToken{ denom:"ujuno".to_string() }, amount:Uint128::new(1000) },
+            min_increment: Uint128::new(100),
+            end_time: mock_env().block.time.seconds() + 1000
         });
-        execute(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
-
-        assert_eq!(offerings,vec![QueryOfferingsResult{
-            id:"1".to_string(),
+        let msg = ExecuteMsg::ReceiveNft(Cw721ReceiveMsg{
+            sender:"seller".to_string(),
             token_id:"Hope.2".to_string(),
-            list_price:Asset { denom: "ujuno".to_string(),  amount:Uint128::new(2000000) },
-            seller:"buyer2".to_string()
-        }]);
-
-        let juno_tvl = query_get_tvl(deps.as_ref(),"hope1_address".to_string(),"ujuno".to_string()).unwrap();
-        let hope_tvl = query_get_tvl(deps.as_ref(),"hope1_address".to_string(),"hope".to_string()).unwrap();
-        let osmos_tvl = query_get_tvl(deps.as_ref(),"hope1_address".to_string(),"osmos".to_string()).unwrap();
-        let raw_tvl  = query_get_tvl(deps.as_ref(),"hope1_address".to_string(),"raw".to_string()).unwrap();
-        println!("{}","juno".to_string());
-        assert_eq!(juno_tvl,Uint128::new(1000000));
-        
-        println!("{}","hope".to_string());
-        assert_eq!(hope_tvl,Uint128::new(2000000));
-
-        println!("{}","osmos".to_string());
-        assert_eq!(osmos_tvl,Uint128::new(2000000));
-
-         println!("{}","raw".to_string());
-        assert_eq!(raw_tvl,Uint128::new(2000000));
-
-        let collection_info = query_collection_info(deps.as_ref(), "hope1_address".to_string()).unwrap();
-        assert_eq!(collection_info.sale_id,4);
-        let _sale_history = query_get_history(deps.as_ref(), "hope1_address".to_string(), vec!["1".to_string(),"2".to_string(),"3".to_string(),"4".to_string()]).unwrap();
-        
-        let tvl_all = query_all_tvl(deps.as_ref(), "hope1_address".to_string(), vec!["ujuno".to_string(),"hope".to_string(),"osmos".to_string(),"xyz".to_string()]).unwrap();
-        assert_eq!(tvl_all,vec![TvlInfo{
-            denom:"ujuno".to_string(),
-            amount:Uint128::new(1000000)
-        },TvlInfo{
-            denom:"hope".to_string(),
-            amount:Uint128::new(2000000)
-        },TvlInfo{
-            denom:"osmos".to_string(),
-            amount:Uint128::new(2000000)
-        },TvlInfo{
-            denom:"xyz".to_string(),
-            amount:Uint128::new(0)
-        }]);
+            msg:to_binary(&hook).unwrap()
+        });
+        execute(deps.as_mut(), mock_env(), mock_info("hope1_address", &[]), msg).unwrap();
 
-         let info = mock_info("owner", &[]);
-        let msg = ExecuteMsg::SetTvl { address: "hope1_address".to_string(), tvl: vec![TvlInfo{
-            denom:"ujuno".to_string(),
-            amount:Uint128::new(0)
+        // Live offerings can't be overwritten
+        let msg = ExecuteMsg::SetOfferings { address:"hope1_address".to_string(), offering:vec![QueryOfferingsResult {
+            id:"5".to_string(),
+            token_id:"Hope.1".to_string(),
+            list_price:Asset { info:AssetInfo::NativeToken{ denom:"ujuno".to_string() }, amount:Uint128::new(1) },
+            seller:"owner".to_string(),
+            dutch_auction:None,
+            expires_at:None
         }] };
-        execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+        let err = execute(deps.as_mut(), mock_env(), mock_info("owner", &[]), msg).unwrap_err();
+        assert!(matches!(err, ContractError::OfferingExists {}));
+
+        // Nor can tokens in auction or deposited by someone else be listed for another seller
+        let set_offering = |id: &str, token_id: &str, seller: &str| ExecuteMsg::SetOfferings { address:"hope1_address".to_string(), offering:vec![QueryOfferingsResult {
+            id:id.to_string(),
+            token_id:token_id.to_string(),
+            list_price:Asset { info:AssetInfo::NativeToken{ denom:"ujuno".to_string() }, amount:Uint128::new(1) },
+            seller:seller.to_string(),
+            dutch_auction:None,
+            expires_at:None
+        }] };
+        let err = execute(deps.as_mut(), mock_env(), mock_info("owner", &[]), set_offering("5", "Hope.2", "owner")).unwrap_err();
+        assert!(matches!(err, ContractError::NftInUse { .. }));
+        let msg = ExecuteMsg::ReceiveNft(Cw721ReceiveMsg{
+            sender:"seller".to_string(),
+            token_id:"Hope.3".to_string(),
+            msg:to_binary(&cw721_msg).unwrap()
+        });
+        execute(deps.as_mut(), mock_env(), mock_info("hope1_address", &[]), msg).unwrap();
+        remove_offering(deps.as_mut().storage, "hope1_address", 2).unwrap();
+        let err = execute(deps.as_mut(), mock_env(), mock_info("owner", &[]), set_offering("5", "Hope.3", "owner")).unwrap_err();
+        assert!(matches!(err, ContractError::DepositorMismatch { .. }));
+        // Tokens without a deposit record can't get a seller either
+        let msg = ExecuteMsg::GrantRole { role:Role::Migrator, address:"migrator".to_string() };
+        execute(deps.as_mut(), mock_env(), mock_info("owner", &[]), msg).unwrap();
+        let err = execute(deps.as_mut(), mock_env(), mock_info("migrator", &[]), set_offering("5", "Hope.7", "migrator")).unwrap_err();
+        assert!(matches!(err, ContractError::DepositorMismatch { .. }));
+        assert!(!DEPOSITS.has(deps.as_ref().storage, ("hope1_address", "Hope.7")));
+        execute(deps.as_mut(), mock_env(), mock_info("migrator", &[]), set_offering("5", "Hope.3", "seller")).unwrap();
+
+        let propose = |token_ids: Vec<&str>| ExecuteMsg::ProposeNftTransfer {
+            nft_address:"hope1_address".to_string(),
+            token_ids:token_ids.into_iter().map(String::from).collect(),
+            recipient:"rescue".to_string()
+        };
+        let err = execute(deps.as_mut(), mock_env(), mock_info("someone", &[]), propose(vec!["Hope.9"])).unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized {}));
+        let err = execute(deps.as_mut(), mock_env(), mock_info("owner", &[]), propose(vec!["Hope.2"])).unwrap_err();
+        assert!(matches!(err, ContractError::NftInUse { .. }));
+        execute(deps.as_mut(), mock_env(), mock_info("owner", &[]), propose(vec!["Hope.1", "Hope.9"])).unwrap();
+        assert_eq!(token_transfers(deps.as_ref().storage, "hope1_address", "Hope.9").unwrap(), vec![1]);
+        let err = execute(deps.as_mut(), mock_env(), mock_info("owner", &[]), set_offering("6", "Hope.9", "owner")).unwrap_err();
+        assert!(matches!(err, ContractError::NftInUse { .. }));
+        let transfer = query_nft_transfer(deps.as_ref(), 1).unwrap().unwrap();
+        assert_eq!(transfer.executable_at, mock_env().block.time.seconds() + NFT_TRANSFER_DELAY);
+        assert_eq!(query_list_nft_transfers(deps.as_ref(), None, None).unwrap(), vec![transfer]);
+
+        let execute_transfer = ExecuteMsg::ExecuteNftTransfer { transfer_id:1 };
+        let err = execute(deps.as_mut(), mock_env(), mock_info("owner", &[]), execute_transfer.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::Timelocked { .. }));
+        let mut env = mock_env();
+        env.block.time = env.block.time.plus_seconds(NFT_TRANSFER_DELAY);
+        let err = execute(deps.as_mut(), env.clone(), mock_info("owner", &[]), execute_transfer.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::NftInUse { .. }));
+
+        // The seller of the listed token agrees, its offering is closed by the transfer
+        let err = execute(deps.as_mut(), mock_env(), mock_info("someone", &[]), ExecuteMsg::ConsentNftTransfer { transfer_id:1 }).unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized {}));
+        execute(deps.as_mut(), mock_env(), mock_info("seller", &[]), ExecuteMsg::ConsentNftTransfer { transfer_id:1 }).unwrap();
+        let res = execute(deps.as_mut(), env, mock_info("owner", &[]), execute_transfer).unwrap();
+        assert_eq!(res.messages.len(), 2);
+        assert_eq!(res.messages[0].msg, CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr:"hope1_address".to_string(),
+            funds:vec![],
+            msg:to_binary(&Cw721ExecuteMsg::TransferNft { recipient:"rescue".to_string(), token_id:"Hope.1".to_string() }).unwrap()
+        }));
+        assert_eq!(query_offering_by_token(deps.as_ref(), "hope1_address".to_string(), "Hope.1".to_string()).unwrap(), None);
+        assert_eq!(query_get_ids(deps.as_ref(), "hope1_address".to_string()).unwrap(), vec!["5"]);
+        assert_eq!(query_collection_info(deps.as_ref(), "hope1_address".to_string()).unwrap().num_offerings, 1);
+        assert!(query_list_nft_transfers(deps.as_ref(), None, None).unwrap().is_empty());
+        assert!(token_transfers(deps.as_ref().storage, "hope1_address", "Hope.9").unwrap().is_empty());
+    }
+    #[test]
+    fn reconcile_escrow() {
+        let mut deps = mock_dependencies();
+        setup_collection(deps.as_mut());
+        deps.querier.update_wasm(|query| match query {
+            WasmQuery::Smart { contract_addr, msg } if contract_addr == "hope1_address" => {
+                match from_binary(msg).unwrap() {
+                    Cw721QueryMsg::Tokens { start_after, limit, .. } => {
+                        let tokens = ["Hope.1", "Hope.2"].iter()
+                            .filter(|token_id| !matches!(start_after.as_deref(), Some(start) if **token_id <= start))
+                            .take(limit.unwrap() as usize)
+                            .map(|token_id| token_id.to_string())
+                            .collect();
+                        SystemResult::Ok(ContractResult::Ok(to_binary(&TokensResponse { tokens }).unwrap()))
+                    }
+                    Cw721QueryMsg::OwnerOf { token_id, .. } if token_id == "Hope.3" => {
+                        SystemResult::Ok(ContractResult::Err("token not found".to_string()))
+                    }
+                    Cw721QueryMsg::OwnerOf { .. } => {
+                        let owner = OwnerOfResponse { owner:mock_env().contract.address.to_string(), approvals:vec![] };
+                        SystemResult::Ok(ContractResult::Ok(to_binary(&owner).unwrap()))
+                    }
+                    _ => panic!("unexpected cw721 query"),
+                }
+            }
+            _ => SystemResult::Err(SystemError::UnsupportedRequest { kind: "wasm".to_string() }),
+        });
 
-        let tvl_all = query_all_tvl(deps.as_ref(), "hope1_address".to_string(), vec!["ujuno".to_string()]).unwrap();
-        assert_eq!(tvl_all,vec![TvlInfo{
-            denom:"ujuno".to_string(),
-            amount:Uint128::new(0)
-        }]);
-       
+        for (seller, token_id) in [("seller", "Hope.1"), ("seller2", "Hope.2"), ("seller3", "Hope.3")] {
+            let cw721_msg = SellNft{
+                list_price:Asset { info:AssetInfo::NativeToken{ denom:"ujuno".to_string() }, amount:Uint128::new(1000) },
+                expires_at:None
+            };
+            let msg = ExecuteMsg::ReceiveNft(Cw721ReceiveMsg{
+                sender:seller.to_string(),
+                token_id:token_id.to_string(),
+                msg:to_binary(&cw721_msg).unwrap()
+            });
+            execute(deps.as_mut(), mock_env(), mock_info("hope1_address", &[]), msg).unwrap();
+        }
+        // Lose the offering of Hope.2, the cw721 contract doesn't know Hope.3 anymore
+        remove_offering(deps.as_mut().storage, "hope1_address", 2).unwrap();
+
+        let report = query_reconcile_escrow(deps.as_ref(), mock_env(), "hope1_address".to_string(), None, None).unwrap();
+        assert_eq!(report.orphaned, vec![OrphanedNft { token_id:"Hope.2".to_string(), depositor:Some("seller2".to_string()) }]);
+        assert_eq!(report.next_start_after, None);
+        let page = |start_after: Option<&str>| query_reconcile_escrow(deps.as_ref(), mock_env(), "hope1_address".to_string(), start_after.map(String::from), Some(1)).unwrap();
+        let first = page(None);
+        assert!(first.orphaned.is_empty());
+        assert_eq!(first.next_start_after, Some("Hope.1".to_string()));
+        let second = page(Some("Hope.1"));
+        assert_eq!(second.orphaned.len(), 1);
+        assert_eq!(second.next_start_after, Some("Hope.2".to_string()));
+        assert!(page(Some("Hope.2")).orphaned.is_empty());
+
+        let dangling = query_dangling_offerings(deps.as_ref(), mock_env(), "hope1_address".to_string(), None, None).unwrap();
+        let ids: Vec<String> = dangling.offerings.iter().map(|offering| offering.id.clone()).collect();
+        assert_eq!(ids, vec!["3".to_string()]);
+        assert_eq!(dangling.next_start_after, None);
+        let dangling = query_dangling_offerings(deps.as_ref(), mock_env(), "hope1_address".to_string(), None, Some(1)).unwrap();
+        assert!(dangling.offerings.is_empty());
+        assert_eq!(dangling.next_start_after, Some("1".to_string()));
+
+        let claim = |token_id: &str| ExecuteMsg::ClaimOrphanedNft { nft_address:"hope1_address".to_string(), token_id:token_id.to_string() };
+        let err = execute(deps.as_mut(), mock_env(), mock_info("seller", &[]), claim("Hope.2")).unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized {}));
+        let err = execute(deps.as_mut(), mock_env(), mock_info("seller", &[]), claim("Hope.1")).unwrap_err();
+        assert!(matches!(err, ContractError::NftInUse { .. }));
+        // A pending transfer proposal doesn't hold up the depositor, it is cancelled instead
+        let msg = ExecuteMsg::ProposeNftTransfer { nft_address:"hope1_address".to_string(), token_ids:vec!["Hope.2".to_string()], recipient:"rescue".to_string() };
+        execute(deps.as_mut(), mock_env(), mock_info("owner", &[]), msg).unwrap();
+        let res = execute(deps.as_mut(), mock_env(), mock_info("seller2", &[]), claim("Hope.2")).unwrap();
+        assert!(res.attributes.contains(&attr("cancelled_transfers", "1")));
+        assert!(query_list_nft_transfers(deps.as_ref(), None, None).unwrap().is_empty());
+        assert_eq!(res.messages[0].msg, CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr:"hope1_address".to_string(),
+            funds:vec![],
+            msg:to_binary(&Cw721ExecuteMsg::TransferNft { recipient:"seller2".to_string(), token_id:"Hope.2".to_string() }).unwrap()
+        }));
+        let err = execute(deps.as_mut(), mock_env(), mock_info("seller2", &[]), claim("Hope.2")).unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized {}));
+
+        // Withdrawing clears the deposit along with the offering
+        execute(deps.as_mut(), mock_env(), mock_info("seller", &[]), ExecuteMsg::WithdrawNft { offering_id:"1".to_string(), nft_address:"hope1_address".to_string() }).unwrap();
+        let err = execute(deps.as_mut(), mock_env(), mock_info("seller", &[]), claim("Hope.1")).unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized {}));
     }
 }
-    
\ No newline at end of file
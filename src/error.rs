@@ -1,6 +1,8 @@
 use cosmwasm_std::StdError;
 use thiserror::Error;
 
+use crate::state::Role;
+
 #[derive(Error, Debug)]
 pub enum ContractError {
     #[error("{0}")]
@@ -39,4 +41,70 @@ pub enum ContractError {
 
     #[error("Escrow not expired")]
     NotExpired {},
+
+    #[error("Fee must be lower than 1")]
+    InvalidFee {},
+
+    #[error("Auction end time must be in the future")]
+    InvalidEndTime {},
+
+    #[error("Floor price exceeds start price")]
+    InvalidFloorPrice {},
+
+    #[error("Dutch auction prices cannot be updated")]
+    DutchAuctionPrice {},
+
+    #[error("Auction has ended")]
+    AuctionEnded {},
+
+    #[error("Auction has not ended yet")]
+    AuctionNotEnded {},
+
+    #[error("Bid is lower than the minimum accepted bid")]
+    BidTooLow {},
+
+    #[error("Expiration must be in the future")]
+    InvalidExpiration {},
+
+    #[error("Offer has expired")]
+    OfferExpired {},
+
+    #[error("Offering has expired")]
+    OfferingExpired {},
+
+    #[error("Offering holds a different token")]
+    TokenIdMismatch {},
+
+    #[error("Quantity must be positive")]
+    InvalidQuantity {},
+
+    #[error("Token {token_id} backs an active offering, auction or transfer")]
+    NftInUse { token_id: String },
+
+    #[error("Token {token_id} wasn't deposited by the given seller")]
+    DepositorMismatch { token_id: String },
+
+    #[error("Transfer is timelocked until {executable_at}")]
+    Timelocked { executable_at: u64 },
+
+    #[error("Offering already exists")]
+    OfferingExists {},
+
+    #[error("Trading is paused")]
+    Paused {},
+
+    #[error("Sender lacks the {role} role")]
+    MissingRole { role: Role },
+
+    #[error("No ownership transfer is pending")]
+    NoPendingOwner {},
+
+    #[error("Ownership proposal has expired")]
+    ProposalExpired {},
+
+    #[error("Cannot migrate from contract {contract}")]
+    WrongContract { contract: String },
+
+    #[error("Cannot migrate from version {from} to {to}")]
+    InvalidVersion { from: String, to: String },
 }
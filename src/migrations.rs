@@ -0,0 +1,312 @@
+use cosmwasm_std::{Order, StdError, StdResult, Storage, Uint128};
+use cw_storage_plus::Map;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+
+use crate::asset::{Asset, AssetInfo};
+use crate::contract::add_volume;
+use crate::error::ContractError;
+use crate::state::{offerings, sale_history, CollectionInfo, DutchAuction, GlobalStats, Offering, SaleInfo, VolumeInfo,
+    auctions, Auction, BUYERS, COLLECTIONINFO, CONFIG, DEPOSITS, GLOBAL_STATS, GLOBAL_VOLUMES, PRICEINFO, SELLERS, TOKENADDRESS, TVL, VOLUMES};
+
+type Migration = fn(&mut dyn Storage) -> StdResult<()>;
+
+/// Storage migrations by the version that introduced them, in ascending order. Every step
+/// leaves already converted entries untouched so a failed upgrade can be retried.
+const MIGRATIONS: &[(&str, Migration)] = &[
+    ("0.2.0", migrate_v0_2_0),
+];
+
+/// Runs the migrations of every version after `from` up to `to`, returning their versions
+pub fn migrate_storage(storage: &mut dyn Storage, from: &str, to: &str) -> Result<Vec<&'static str>, ContractError> {
+    let invalid = || ContractError::InvalidVersion { from: from.to_string(), to: to.to_string() };
+    let (previous, current) = match (parse_version(from), parse_version(to)) {
+        (Some(previous), Some(current)) if previous <= current => (previous, current),
+        _ => return Err(invalid()),
+    };
+
+    let mut applied = vec![];
+    for (version, migration) in MIGRATIONS {
+        let step = parse_version(version).ok_or_else(invalid)?;
+        if previous < step && step <= current {
+            migration(storage)?;
+            applied.push(*version);
+        }
+    }
+    Ok(applied)
+}
+
+/// (major, minor, patch) of a `major.minor.patch` version
+fn parse_version(version: &str) -> Option<(u64, u64, u64)> {
+    let mut parts = version.split('.').map(|part| part.parse::<u64>().ok());
+    match (parts.next(), parts.next(), parts.next(), parts.next()) {
+        (Some(Some(major)), Some(Some(minor)), Some(Some(patch)), None) => Some((major, minor, patch)),
+        _ => None,
+    }
+}
+
+/// Asset prices, offering and sale indexes, deposits, per asset volumes and marketplace statistics
+fn migrate_v0_2_0(storage: &mut dyn Storage) -> StdResult<()> {
+    migrate_state(storage)?;
+    migrate_offerings(storage)?;
+    migrate_asset_prices(storage)?;
+    migrate_auction_indexes(storage)?;
+    migrate_deposits(storage)?;
+    migrate_sale_indexes(storage)?;
+    migrate_volumes(storage)?;
+    migrate_global_stats(storage)
+}
+
+/// `State` gained the marketplace fee, fees are collected by the owner until configured
+fn migrate_state(storage: &mut dyn Storage) -> StdResult<()> {
+    let mut state = CONFIG.load(storage)?;
+    if state.fee_collector.is_empty() {
+        state.fee_collector = state.owner.clone();
+        CONFIG.save(storage, &state)?;
+    }
+    Ok(())
+}
+
+/// Listing price either as `asset::Asset` or as stored before it, with cw20 tokens named
+/// by their `TOKENADDRESS` symbol in `denom`
+#[derive(Serialize, Deserialize)]
+pub(crate) struct StoredPrice {
+    pub(crate) info: Option<AssetInfo>,
+    pub(crate) denom: Option<String>,
+    pub(crate) amount: Uint128,
+}
+
+#[derive(Serialize, Deserialize)]
+pub(crate) struct StoredOffering {
+    pub(crate) token_id: String,
+    pub(crate) seller: String,
+    pub(crate) list_price: StoredPrice,
+    pub(crate) dutch_auction: Option<DutchAuction>,
+    pub(crate) expires_at: Option<u64>,
+}
+
+/// Offerings keyed by decimal string ids, superseded by `offerings()`
+pub(crate) const STORED_OFFERINGS: Map<(&str,&str), StoredOffering> = Map::new("offerings");
+
+/// Registered cw20 contract addresses by symbol
+fn token_symbols(storage: &dyn Storage) -> StdResult<HashMap<String, String>> {
+    TOKENADDRESS
+        .range(storage, None, None, Order::Ascending)
+        .map(|item| item.map(|(address, symbol)| (symbol, address)))
+        .collect()
+}
+
+/// Moves offerings to integer ids, converting symbol denominated prices to `AssetInfo`.
+/// Offerings used to be packed into ids 1..=offering_id by moving the last offering into
+/// freed ids. Those ids stay valid, ids that aren't numbers get fresh ones and the live
+/// count is rebuilt from the moved offerings.
+fn migrate_offerings(storage: &mut dyn Storage) -> StdResult<()> {
+    let symbols = token_symbols(storage)?;
+    let collections: Vec<(String, CollectionInfo)> = COLLECTIONINFO
+        .range(storage, None, None, Order::Ascending)
+        .collect::<StdResult<_>>()?;
+
+    for (address, mut collection_info) in collections {
+        let stored: Vec<(String, StoredOffering)> = STORED_OFFERINGS
+            .prefix(&address)
+            .range(storage, None, None, Order::Ascending)
+            .collect::<StdResult<_>>()?;
+        let max_id = stored.iter().filter_map(|(id, _)| id.parse::<u64>().ok()).max().unwrap_or_default();
+        collection_info.offering_id = collection_info.offering_id.max(max_id);
+
+        for (id, offering) in stored {
+            let list_price = match offering.list_price {
+                StoredPrice { info: Some(info), amount, .. } => Asset { info, amount },
+                StoredPrice { denom: Some(denom), amount, .. } => match symbols.get(&denom) {
+                    Some(contract_addr) => Asset { info: AssetInfo::Token { contract_addr: contract_addr.clone() }, amount },
+                    None => Asset { info: AssetInfo::NativeToken { denom }, amount },
+                },
+                StoredPrice { .. } => return Err(StdError::generic_err(format!("Offering {} has no price", id))),
+            };
+            STORED_OFFERINGS.remove(storage, (&address, &id));
+            let offering_id = id.parse::<u64>().unwrap_or_else(|_| {
+                collection_info.offering_id += 1;
+                collection_info.offering_id
+            });
+            offerings().save(storage, (&address, offering_id), &Offering {
+                nft_address: address.clone(),
+                token_id: offering.token_id,
+                seller: offering.seller,
+                list_price,
+                dutch_auction: offering.dutch_auction,
+                expires_at: offering.expires_at
+            })?;
+        }
+
+        collection_info.num_offerings = offerings()
+            .prefix(&address)
+            .keys(storage, None, None, Order::Ascending)
+            .count() as u64;
+        COLLECTIONINFO.save(storage, &address, &collection_info)?;
+    }
+    Ok(())
+}
+
+/// Converts symbol denominated stats to `AssetInfo`, cw20 tokens are keyed by contract
+/// address from now on. Already converted entries are left untouched.
+fn migrate_asset_prices(storage: &mut dyn Storage) -> StdResult<()> {
+    let symbols = token_symbols(storage)?;
+
+    let tvls: Vec<((String, String), Uint128)> = TVL
+        .range(storage, None, None, Order::Ascending)
+        .collect::<StdResult<_>>()?;
+    for ((address, denom), amount) in tvls {
+        if let Some(contract_addr) = symbols.get(&denom) {
+            TVL.remove(storage, (&address, &denom));
+            TVL.update(storage, (&address, contract_addr), |tvl| -> StdResult<_> {
+                Ok(tvl.unwrap_or_default() + amount)
+            })?;
+        }
+    }
+
+    let history: Vec<((String, String), SaleInfo)> = sale_history()
+        .range(storage, None, None, Order::Ascending)
+        .collect::<StdResult<_>>()?;
+    for ((address, id), mut sale) in history {
+        if let Some(contract_addr) = symbols.get(&sale.denom) {
+            sale.denom = contract_addr.clone();
+            sale_history().save(storage, (&address, &id), &sale)?;
+        }
+    }
+    Ok(())
+}
+
+/// Saves every auction again through the indexed map to build its token index, auctions
+/// stored before the index also lack their `nft_address`.
+fn migrate_auction_indexes(storage: &mut dyn Storage) -> StdResult<()> {
+    let stored: Vec<((String, String), Auction)> = auctions()
+        .range(storage, None, None, Order::Ascending)
+        .collect::<StdResult<_>>()?;
+    for ((address, id), mut auction) in stored {
+        auction.nft_address = address.clone();
+        auctions().save(storage, (&address, &id), &auction)?;
+    }
+    Ok(())
+}
+
+/// Escrowed nfts were received from the seller of their offering or auction. Orphans, escrowed
+/// nfts whose offering was overwritten by the id packing of earlier versions, lost their seller.
+/// They are recorded for the buyer of their latest sale, the last owner the marketplace knows of.
+/// Orphans never sold here get no record and can only be recovered through `ProposeNftTransfer`,
+/// so do orphans that changed hands outside the marketplace after their latest sale once the
+/// recorded buyer is found not to be the depositor.
+fn migrate_deposits(storage: &mut dyn Storage) -> StdResult<()> {
+    let mut deposits: Vec<(String, String, String)> = vec![];
+    for item in offerings().range(storage, None, None, Order::Ascending) {
+        let (_, offering) = item?;
+        deposits.push((offering.nft_address, offering.token_id, offering.seller));
+    }
+    for item in auctions().range(storage, None, None, Order::Ascending) {
+        let (_, auction) = item?;
+        deposits.push((auction.nft_address, auction.token_id, auction.seller));
+    }
+
+    // Buyer of the latest sale of every token, sale ids are decimal strings
+    let mut last_sales: BTreeMap<(String, String), ((u64, u64), String)> = BTreeMap::new();
+    for item in sale_history().range(storage, None, None, Order::Ascending) {
+        let ((address, id), sale) = item?;
+        let order = (sale.time, id.parse::<u64>().unwrap_or_default());
+        let last_sale = last_sales.entry((address, sale.token_id)).or_insert((order, sale.to.clone()));
+        if order > last_sale.0 {
+            *last_sale = (order, sale.to);
+        }
+    }
+    deposits.extend(last_sales.into_iter().map(|((address, token_id), (_, buyer))| (address, token_id, buyer)));
+
+    for (address, token_id, seller) in deposits {
+        if !DEPOSITS.has(storage, (&address, &token_id)) {
+            DEPOSITS.save(storage, (&address, &token_id), &seller)?;
+        }
+    }
+    Ok(())
+}
+
+/// Saves every sale again through the indexed map to build its indexes.
+fn migrate_sale_indexes(storage: &mut dyn Storage) -> StdResult<()> {
+    let stored: Vec<((String, String), SaleInfo)> = sale_history()
+        .range(storage, None, None, Order::Ascending)
+        .collect::<StdResult<_>>()?;
+    for ((address, id), sale) in stored {
+        sale_history().save(storage, (&address, &id), &sale)?;
+    }
+    Ok(())
+}
+
+/// `PRICEINFO` lumped every native denom and every cw20 together, the per asset volumes
+/// of those collections are rebuilt from their sale history.
+fn migrate_volumes(storage: &mut dyn Storage) -> StdResult<()> {
+    let collections: Vec<String> = PRICEINFO
+        .keys(storage, None, None, Order::Ascending)
+        .collect::<StdResult<_>>()?;
+
+    for address in collections {
+        let history: Vec<SaleInfo> = sale_history()
+            .prefix(&address)
+            .range(storage, None, None, Order::Ascending)
+            .map(|item| item.map(|(_, sale)| sale))
+            .collect::<StdResult<_>>()?;
+        for sale in history {
+            let amount = sale.amount;
+            let asset_info = if TOKENADDRESS.has(storage, &sale.denom) {
+                AssetInfo::Token { contract_addr: sale.denom }
+            } else {
+                AssetInfo::NativeToken { denom: sale.denom }
+            };
+            VOLUMES.update(storage, (&address, &asset_info.to_string()), |volume| add_volume(volume, &asset_info, amount))?;
+        }
+        PRICEINFO.remove(storage, &address);
+    }
+    Ok(())
+}
+
+/// Recomputes the marketplace-wide statistics from the per collection records.
+fn migrate_global_stats(storage: &mut dyn Storage) -> StdResult<()> {
+    let mut stats = GlobalStats::default();
+
+    let collections: Vec<CollectionInfo> = COLLECTIONINFO
+        .range(storage, None, None, Order::Ascending)
+        .map(|item| item.map(|(_, collection_info)| collection_info))
+        .collect::<StdResult<_>>()?;
+    stats.active_listings = collections.iter().map(|collection_info| collection_info.num_offerings).sum();
+
+    let denoms: Vec<String> = GLOBAL_VOLUMES
+        .keys(storage, None, None, Order::Ascending)
+        .collect::<StdResult<_>>()?;
+    for denom in denoms {
+        GLOBAL_VOLUMES.remove(storage, &denom);
+    }
+    let volumes: Vec<((String, String), VolumeInfo)> = VOLUMES
+        .range(storage, None, None, Order::Ascending)
+        .collect::<StdResult<_>>()?;
+    for ((_, denom), volume) in volumes {
+        stats.total_sales += volume.sales;
+        GLOBAL_VOLUMES.update(storage, &denom, |global| -> StdResult<_> {
+            let mut global = global.unwrap_or(VolumeInfo {
+                info: volume.info.clone(),
+                amount: Uint128::zero(),
+                sales: 0
+            });
+            global.amount += volume.amount;
+            global.sales += volume.sales;
+            Ok(global)
+        })?;
+    }
+
+    let history: Vec<SaleInfo> = sale_history()
+        .range(storage, None, None, Order::Ascending)
+        .map(|item| item.map(|(_, sale)| sale))
+        .collect::<StdResult<_>>()?;
+    for sale in history {
+        BUYERS.save(storage, &sale.to, &true)?;
+        SELLERS.save(storage, &sale.from, &true)?;
+    }
+    stats.unique_buyers = BUYERS.keys(storage, None, None, Order::Ascending).count() as u64;
+    stats.unique_sellers = SELLERS.keys(storage, None, None, Order::Ascending).count() as u64;
+
+    GLOBAL_STATS.save(storage, &stats)
+}
@@ -2,32 +2,71 @@
 use cw20::Cw20ReceiveMsg;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use crate::{state::{Asset,UserInfo, TvlInfo, SaleInfo}, package::QueryOfferingsResult};
-use cosmwasm_std::{Decimal};
+use crate::{state::{UserInfo, TvlInfo, SaleInfo, FeeTier, RoyaltyMode, Role}, package::QueryOfferingsResult, asset::{Asset, AssetInfo}};
+use cosmwasm_std::{Decimal, Uint128};
 use cw721::Cw721ReceiveMsg;
 
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct InstantiateMsg {
   pub  owner:String,
+  pub  fee:Option<Decimal>,
+  /// Defaults to the owner
+  pub  fee_collector:Option<String>,
 }
 
+/// Storage is upgraded from the version recorded with cw2 by the previous code
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct MigrateMsg {}
+
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub enum ExecuteMsg {
  ReceiveNft(Cw721ReceiveMsg),
  Receive(Cw20ReceiveMsg),
- BuyNft{offering_id:String,nft_address:String},
+ BuyNft{offering_id:String,nft_address:String,token_id:Option<String>},
  WithdrawNft{offering_id:String,nft_address:String},
- ChangeOwner{address:String},
+ /// Proposes `address` as owner, ownership moves once it accepts
+ ProposeOwner{address:String,expires:Option<u64>},
+ AcceptOwnership{},
+ CancelProposal{},
+ /// Leaves the contract without owner and revokes every role, disabling every admin function for good
+ RenounceOwnership{},
+ /// Pauses trading on `nft_address`, or on every collection when omitted. Offerings can still be withdrawn
+ /// and ended auctions nobody bid on settled
+ Pause{nft_address:Option<String>},
+ Unpause{nft_address:Option<String>},
+ GrantRole{role:Role,address:String},
+ RevokeRole{role:Role,address:String},
  AddTokenAddress{symbol:String,address:String},
- AddCollection{royalty_portion:Decimal,members:Vec<UserInfo>,nft_address:String,offering_id:u64,sale_id:u64},
- UpdateCollection{royalty_portion:Decimal,members:Vec<UserInfo>,nft_address:String},
- FixNft{address:String,token_id:String},
+ /// Re-adding a registered collection never lowers its offering and sale ids
+ AddCollection{royalty_portion:Decimal,members:Vec<UserInfo>,nft_address:String,offering_id:u64,sale_id:u64,fee:Option<Decimal>,royalty_mode:Option<RoyaltyMode>},
+ /// Omitted `fee` and `royalty_mode` keep their current values, `clear_fee` drops the fee override
+ UpdateCollection{royalty_portion:Decimal,members:Vec<UserInfo>,nft_address:String,fee:Option<Decimal>,clear_fee:Option<bool>,royalty_mode:Option<RoyaltyMode>},
+ /// Relists escrowed tokens, every seller must be the recorded depositor of its token
  SetOfferings{address:String,offering:Vec<QueryOfferingsResult>},
  SetTvl{address:String,tvl:Vec<TvlInfo>},
- Migrate{address:String,dest:String,token_id : Vec<String>},
- SetSaleHistory{address:String,history:Vec<SaleInfo>}
+ /// Queues a transfer of escrowed nfts to `recipient`, executable after the timelock
+ ProposeNftTransfer{nft_address:String,token_ids:Vec<String>,recipient:String},
+ /// Lets a listed token of the sender be moved by the transfer, which closes its offering
+ ConsentNftTransfer{transfer_id:u64},
+ ExecuteNftTransfer{transfer_id:u64},
+ CancelNftTransfer{transfer_id:u64},
+ /// Returns an escrowed nft that no offering or auction references to the address that sent it,
+ /// cancelling pending transfer proposals of the nft. Nfts escrowed before 0.2.0 are returned to the
+ /// buyer of their latest sale, those never sold here can only be moved by `ProposeNftTransfer`
+ ClaimOrphanedNft{nft_address:String,token_id:String},
+ /// Appends sales of `address` after its current sale id
+ SetSaleHistory{address:String,history:Vec<SaleInfo>},
+ PlaceBid{nft_address:String,auction_id:String},
+ SettleAuction{nft_address:String,auction_id:String},
+ MakeOffer{nft_address:String,token_id:String,price:Asset,expires:u64},
+ CancelOffer{nft_address:String,token_id:String},
+ MakeCollectionOffer{nft_address:String,price:Asset,quantity:Option<u64>,expires:u64},
+ CancelCollectionOffer{nft_address:String},
+ ReclaimExpired{nft_address:String,offering_id:String},
+ UpdateOffering{nft_address:String,offering_id:String,list_price:Asset},
+ UpdateConfig{fee:Option<Decimal>,fee_collector:Option<String>,fee_tiers:Option<Vec<FeeTier>>}
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
@@ -35,26 +74,138 @@ pub enum ExecuteMsg {
 pub enum QueryMsg {
     /// Returns a human-readable representation of the arbiter.
     GetStateInfo {},
+    /// The proposed owner, if an ownership transfer is pending
+    GetPendingOwner {},
+    GetPauseStatus {},
+    GetNftTransfer {transfer_id:u64},
+    /// Pending nft transfers by id, at most `limit` after `start_after`
+    ListNftTransfers {start_after:Option<u64>,limit:Option<u32>},
+    /// Tokens the contract owns in `nft_address` that no offering or auction references, paged by token id
+    ReconcileEscrow {nft_address:String,start_after:Option<String>,limit:Option<u32>},
+    /// Offerings of `nft_address` whose token the contract doesn't own, paged by offering id
+    DanglingOfferings {nft_address:String,start_after:Option<String>,limit:Option<u32>},
+    /// Roles granted to `address`
+    GetRoles {address:String},
+    /// Addresses holding `role`, besides the owner
+    GetRoleMembers {role:Role},
+    GetFeeInfo {},
+    GetEffectiveFee {address:String,denom:String},
     GetMembers{address:String},
     GetOfferingId{address:String},
     GetSaleHistory{address:String,id:Vec<String>},
-    GetOfferingPage{id :Vec<String>,address:String },
+    /// Sales of a collection by time, `start_after` is the sale id of the previous page's last sale
+    ListSales{nft_address:String,start_after:Option<String>,limit:Option<u32>,descending:Option<bool>},
+    /// Sales of every collection by time, `start_after` is a (nft_address, sale id) pair
+    ListAllSales{start_after:Option<(String,String)>,limit:Option<u32>,descending:Option<bool>},
+    /// Sales bought by `buyer` by time, `start_after` is a (nft_address, sale id) pair
+    SalesByBuyer{buyer:String,start_after:Option<(String,String)>,limit:Option<u32>,descending:Option<bool>},
+    /// Sales sold by `seller` by time, `start_after` is a (nft_address, sale id) pair
+    SalesBySeller{seller:String,start_after:Option<(String,String)>,limit:Option<u32>,descending:Option<bool>},
+    /// Sales of a single token by time, `start_after` is a sale id
+    SalesByToken{nft_address:String,token_id:String,start_after:Option<String>,limit:Option<u32>,descending:Option<bool>},
+    GetOfferingPage{id :Vec<String>,address:String,exclude_expired:Option<bool> },
+    /// Offerings of a collection in numeric id order, at most `limit` after `start_after`
+    ListOfferings{nft_address:String,start_after:Option<String>,limit:Option<u32>},
+    /// Offerings of every collection by address, then numeric id, `start_after` is a (nft_address, offering id) pair
+    ListAllOfferings{start_after:Option<(String,String)>,limit:Option<u32>},
+    /// Offerings listed by `seller`, `start_after` is a (nft_address, offering id) pair
+    OfferingsBySeller{seller:String,start_after:Option<(String,String)>,limit:Option<u32>},
+    /// The offering of a token, if it is listed
+    OfferingByToken{nft_address:String,token_id:String},
+    /// Fixed price offerings of a collection priced in `asset`, sorted by price. `start_after` is the
+    /// (price, offering id) of the previous page's last offering, Dutch auctions are listed by `DutchAuctions`
+    OfferingsByPrice{nft_address:String,asset:AssetInfo,start_after:Option<(Uint128,String)>,limit:Option<u32>,descending:Option<bool>},
+    /// Dutch auction offerings of a collection priced in `asset`, sorted by floor price as their current
+    /// price depends on the block time. `start_after` is the (floor price, offering id) of the previous page's last offering
+    DutchAuctions{nft_address:String,asset:AssetInfo,start_after:Option<(Uint128,String)>,limit:Option<u32>,descending:Option<bool>},
+    /// Cheapest offering of a collection in `asset` that can be bought now
+    FloorPrice{nft_address:String,asset:AssetInfo},
+    /// Legacy totals: `total_juno` counts sales in `ujuno` and `total_hope` sales in the cw20 registered
+    /// as `hope`. Other native, IBC and cw20 denoms are only reported by `GetVolumes`
     GetTradingInfo{address:String},
+    /// Volume and number of sales of a collection for every asset it traded in
+    GetVolumes{address:String},
+    GetGlobalStats{},
     GetCollectionInfo{address:String},
+    /// `symbol` is a native denom, a cw20 contract address or the symbol of a registered cw20 token
     GetTvl{address:String,symbol:String},
-    GetTvlAll{address:String,symbols:Vec<String>}
+    GetTvlAll{address:String,symbols:Vec<String>},
+    GetAuctionIds{address:String},
+    GetAuctionPage{id :Vec<String>,address:String },
+    GetCurrentPrice{address:String,offering_id:String},
+    GetTokenOffers{address:String,token_id:String},
+    GetBidderOffers{bidder:String},
+    GetCollectionOffers{address:String}
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub struct SellNft {
     pub list_price: Asset,
+    pub expires_at: Option<u64>,
+}
+
+
+/// Hook messages of cw721 `SendNft` other than the plain `SellNft` listing
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum NftHookMsg {
+    StartAuction(StartAuction),
+    StartDutchAuction(StartDutchAuction),
+    /// Sells the sent nft to the escrowed offer of `bidder`
+    AcceptOffer { bidder: String },
+    /// Sells the sent nft to the collection-wide offer of `bidder`
+    AcceptCollectionOffer { bidder: String },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct StartAuction {
+    pub reserve_price: Asset,
+    pub min_increment: Uint128,
+    pub end_time: u64,
+}
+
+/// Lists an nft whose price falls from `start_price` to `floor_price` between
+/// `start_time` and `end_time`, in `step` second decrements when set, linearly otherwise
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct StartDutchAuction {
+    pub start_price: Asset,
+    pub floor_price: Uint128,
+    pub start_time: u64,
+    pub end_time: u64,
+    pub step: Option<u64>,
 }
 
+/// Hook messages of cw20 `Send` other than the plain `BuyNft` purchase
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum TokenHookMsg {
+    PlaceBid { nft_address: String, auction_id: String },
+    MakeOffer { nft_address: String, token_id: String, expires: u64 },
+    /// The sent amount is split evenly over `quantity` tokens, one when unset
+    MakeCollectionOffer { nft_address: String, quantity: Option<u64>, expires: u64 },
+}
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub struct BuyNft {
     pub offering_id: String,
-    pub nft_address : String
+    pub nft_address : String,
+    /// Aborts the purchase when the offering holds another token
+    pub token_id: Option<String>
+}
+
+/// CW-2981 royalty extension query of cw721 contracts
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Cw2981QueryMsg {
+    Extension { msg: RoyaltyQueryMsg },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum RoyaltyQueryMsg {
+    RoyaltyInfo { token_id: String, sale_price: Uint128 },
 }
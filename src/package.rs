@@ -1,6 +1,8 @@
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use crate::state::Asset;
+use cosmwasm_std::{Decimal, Uint128};
+use crate::state::{Bid, DutchAuction, FeeTier, SaleInfo, VolumeInfo};
+use crate::asset::Asset;
 
 #[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
 pub struct QueryOfferingsResult {
@@ -8,6 +10,8 @@ pub struct QueryOfferingsResult {
     pub token_id: String,
     pub list_price: Asset,
     pub seller: String,
+    pub dutch_auction: Option<DutchAuction>,
+    pub expires_at: Option<u64>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
@@ -15,4 +19,96 @@ pub struct OfferingsResponse {
     pub offerings: Vec<QueryOfferingsResult>,
 }
 
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct CollectionOffering {
+    pub nft_address: String,
+    pub offering: QueryOfferingsResult,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct AllOfferingsResponse {
+    pub offerings: Vec<CollectionOffering>,
+}
+
 // THIS FILE SHOULD BE EXTRACTED TO ITS OWN PACKAGE PROJECT LIKE CW20 OR CW721
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+pub struct QueryAuctionsResult {
+    pub id: String,
+    pub token_id: String,
+    pub seller: String,
+    pub reserve_price: Asset,
+    pub min_increment: Uint128,
+    pub end_time: u64,
+    pub highest_bid: Option<Bid>,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+pub struct FeeInfo {
+    pub fee: Decimal,
+    pub fee_collector: String,
+    pub fee_tiers: Vec<FeeTier>,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+pub struct RoyaltiesInfoResponse {
+    pub address: String,
+    pub royalty_amount: Uint128,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+pub struct GlobalStatsResponse {
+    pub total_sales: u64,
+    pub active_listings: u64,
+    pub unique_buyers: u64,
+    pub unique_sellers: u64,
+    pub volumes: Vec<VolumeInfo>,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+pub struct FloorPriceResponse {
+    pub offering: QueryOfferingsResult,
+    /// Current price, below the list price for Dutch auctions
+    pub price: Asset,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+pub struct SaleResult {
+    pub id: String,
+    pub sale: SaleInfo,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+pub struct SalesResponse {
+    pub sales: Vec<SaleResult>,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+pub struct PauseStatusResponse {
+    pub global: bool,
+    /// Collections paused on their own
+    pub collections: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+pub struct OrphanedNft {
+    pub token_id: String,
+    /// Sender of the nft, unknown for deposits made before they were recorded
+    pub depositor: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+pub struct EscrowReconciliationResponse {
+    /// Tokens owned by the contract that no offering or auction references
+    pub orphaned: Vec<OrphanedNft>,
+    /// `start_after` of the next page, none once all tokens were compared
+    pub next_start_after: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+pub struct DanglingOfferingsResponse {
+    /// Offerings whose token the contract doesn't own
+    pub offerings: Vec<QueryOfferingsResult>,
+    /// `start_after` of the next page, none once all offerings were checked
+    pub next_start_after: Option<String>,
+}
@@ -1,36 +1,373 @@
 use cosmwasm_std::{Uint128, Decimal};
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use cw_storage_plus::{Item,Map};
+use cosmwasm_std::{Order, StdError, StdResult, Storage};
+use cw_storage_plus::{Item,Map,IndexedMap,MultiIndex,IndexList,Index,CwIntKey};
+use crate::asset::{Asset, AssetInfo};
+use std::convert::TryInto;
+use std::fmt;
 
 pub const CONFIG: Item<State> = Item::new("config_state");
+pub const NFT_TRANSFER_COUNT: Item<u64> = Item::new("nft_transfer_count");
+/// Halts trading on every collection, resp. on single collections
+pub const PAUSED: Item<bool> = Item::new("paused");
+pub const PAUSED_COLLECTIONS: Map<&str,bool> = Map::new("paused_collections");
+/// Admin roles granted by the owner, keyed by (role, address)
+pub const ROLES: Map<(&str,&str), bool> = Map::new("roles");
+/// Ownership transfer waiting to be accepted by the proposed owner
+pub const PENDING_OWNER: Item<PendingOwner> = Item::new("pending_owner");
 pub const MEMBERS : Map<&str,Vec<UserInfo>> = Map::new("config_members");
-pub const OFFERINGS: Map<(&str,&str), Offering> = Map::new("offerings");
-pub const SALEHISTORY : Map<(&str,&str), SaleInfo> = Map::new("sale");
+/// Superseded by `VOLUMES`, only read when migrating
 pub const PRICEINFO : Map<&str,PriceInfo> = Map::new("price_info");
+/// Trading volume keyed by (nft_address, native denom or cw20 contract address)
+pub const VOLUMES : Map<(&str,&str),VolumeInfo> = Map::new("volumes");
+/// Marketplace-wide totals across all collections
+pub const GLOBAL_STATS : Item<GlobalStats> = Item::new("global_stats");
+pub const GLOBAL_VOLUMES : Map<&str,VolumeInfo> = Map::new("global_volumes");
+/// Addresses that bought, resp. sold, at least once
+pub const BUYERS : Map<&str,bool> = Map::new("buyers");
+pub const SELLERS : Map<&str,bool> = Map::new("sellers");
 pub const COLLECTIONINFO : Map<&str, CollectionInfo> = Map::new("collection_info");
 pub const TOKENADDRESS : Map<&str, String> = Map::new("token_address");
 pub const TVL:Map<(&str,&str),Uint128> = Map::new("tvl_config");
+/// The address each escrowed nft was received from, keyed by (nft_address, token_id). Migrated
+/// orphans are recorded for the buyer of their latest sale
+pub const DEPOSITS: Map<(&str,&str), String> = Map::new("deposits");
+
+/// Indexes every token of a transfer proposal by (nft_address, token_id, transfer id)
+pub struct TransferTokenIndex<'a> {
+    tokens: Map<'a, (String, String, u64), bool>,
+}
+
+impl<'a> TransferTokenIndex<'a> {
+    /// Ids of the pending transfers moving `token_id`
+    pub fn transfer_ids(&self, storage: &dyn Storage, nft_address: &str, token_id: &str) -> StdResult<Vec<u64>> {
+        self.tokens
+            .prefix((nft_address.to_string(), token_id.to_string()))
+            .keys(storage, None, None, Order::Ascending)
+            .collect()
+    }
+}
+
+/// Transfer id of an `nft_transfers` primary key
+fn transfer_id(pk: &[u8]) -> StdResult<u64> {
+    let bytes = pk.try_into().map_err(|_| StdError::invalid_data_size(8, pk.len()))?;
+    Ok(u64::from_cw_bytes(bytes))
+}
+
+impl<'a> Index<NftTransfer> for TransferTokenIndex<'a> {
+    fn save(&self, storage: &mut dyn Storage, pk: &[u8], data: &NftTransfer) -> StdResult<()> {
+        let id = transfer_id(pk)?;
+        for token_id in &data.token_ids {
+            self.tokens.save(storage, (data.nft_address.clone(), token_id.clone(), id), &true)?;
+        }
+        Ok(())
+    }
+
+    fn remove(&self, storage: &mut dyn Storage, pk: &[u8], old_data: &NftTransfer) -> StdResult<()> {
+        let id = transfer_id(pk)?;
+        for token_id in &old_data.token_ids {
+            self.tokens.remove(storage, (old_data.nft_address.clone(), token_id.clone(), id));
+        }
+        Ok(())
+    }
+}
+
+pub struct NftTransferIndexes<'a> {
+    pub token: TransferTokenIndex<'a>,
+}
+
+impl<'a> IndexList<NftTransfer> for NftTransferIndexes<'a> {
+    fn get_indexes(&'_ self) -> Box<dyn Iterator<Item = &'_ dyn Index<NftTransfer>> + '_> {
+        let v: Vec<&dyn Index<NftTransfer>> = vec![&self.token];
+        Box::new(v.into_iter())
+    }
+}
+
+/// Timelocked transfers of escrowed nfts by id, `NFT_TRANSFER_COUNT` is the last id
+pub fn nft_transfers<'a>() -> IndexedMap<'a, u64, NftTransfer, NftTransferIndexes<'a>> {
+    let indexes = NftTransferIndexes {
+        token: TransferTokenIndex { tokens: Map::new("nft_transfers__token") },
+    };
+    IndexedMap::new("nft_transfers", indexes)
+}
+
+/// Escrowed offers for any token of a collection, keyed by (nft_address, bidder)
+pub const COLLECTION_OFFERS: Map<(&str,&str), CollectionOffer> = Map::new("collection_offers");
+
+pub struct TokenOfferIndexes<'a> {
+    pub bidder: MultiIndex<'a, String, TokenOffer, (String, String, String)>,
+}
+
+impl<'a> IndexList<TokenOffer> for TokenOfferIndexes<'a> {
+    fn get_indexes(&'_ self) -> Box<dyn Iterator<Item = &'_ dyn Index<TokenOffer>> + '_> {
+        let v: Vec<&dyn Index<TokenOffer>> = vec![&self.bidder];
+        Box::new(v.into_iter())
+    }
+}
+
+/// ((nft_address, asset), `Offering::pricing`, price key of `Offering::min_price`)
+pub type PriceIndexKey = ((String, String), String, Vec<u8>);
+
+/// Pricing of offerings sold at their list price, resp. of Dutch auction offerings
+pub const FIXED_PRICE: &str = "fixed";
+pub const DUTCH_AUCTION: &str = "dutch";
+
+pub struct OfferingIndexes<'a> {
+    pub seller: MultiIndex<'a, String, Offering, (String, u64)>,
+    pub token: MultiIndex<'a, (String, String), Offering, (String, u64)>,
+    pub price: MultiIndex<'a, PriceIndexKey, Offering, (String, u64)>,
+}
+
+impl<'a> IndexList<Offering> for OfferingIndexes<'a> {
+    fn get_indexes(&'_ self) -> Box<dyn Iterator<Item = &'_ dyn Index<Offering>> + '_> {
+        let v: Vec<&dyn Index<Offering>> = vec![&self.seller, &self.token, &self.price];
+        Box::new(v.into_iter())
+    }
+}
+
+/// Offerings keyed by (nft_address, offering_id), indexed by seller and by (nft_address, token_id).
+/// Ids are stored as integers so offerings range in numeric order, the string keyed offerings of
+/// earlier versions are only read when migrating.
+pub fn offerings<'a>() -> IndexedMap<'a, (&'a str, u64), Offering, OfferingIndexes<'a>> {
+    let indexes = OfferingIndexes {
+        seller: MultiIndex::new(|offering: &Offering| offering.seller.clone(), "offerings_v2", "offerings_v2__seller"),
+        token: MultiIndex::new(
+            |offering: &Offering| (offering.nft_address.clone(), offering.token_id.clone()),
+            "offerings_v2",
+            "offerings_v2__token",
+        ),
+        price: MultiIndex::new(
+            |offering: &Offering| (
+                (offering.nft_address.clone(), offering.list_price.info.to_string()),
+                offering.pricing().to_string(),
+                price_key(offering.min_price()),
+            ),
+            "offerings_v2",
+            "offerings_v2__price",
+        ),
+    };
+    IndexedMap::new("offerings_v2", indexes)
+}
+
+/// Big-endian bytes of `amount`, so keys sort by price
+pub fn price_key(amount: Uint128) -> Vec<u8> {
+    amount.u128().to_be_bytes().to_vec()
+}
+
+/// Sale ids are decimal strings, every index ends in the sale time so entries sort chronologically
+pub struct SaleIndexes<'a> {
+    pub collection: MultiIndex<'a, (String, u64), SaleInfo, (String, String)>,
+    pub buyer: MultiIndex<'a, (String, u64), SaleInfo, (String, String)>,
+    pub seller: MultiIndex<'a, (String, u64), SaleInfo, (String, String)>,
+    pub token: MultiIndex<'a, (String, String, u64), SaleInfo, (String, String)>,
+    pub time: MultiIndex<'a, u64, SaleInfo, (String, String)>,
+}
+
+impl<'a> IndexList<SaleInfo> for SaleIndexes<'a> {
+    fn get_indexes(&'_ self) -> Box<dyn Iterator<Item = &'_ dyn Index<SaleInfo>> + '_> {
+        let v: Vec<&dyn Index<SaleInfo>> = vec![&self.collection, &self.buyer, &self.seller, &self.token, &self.time];
+        Box::new(v.into_iter())
+    }
+}
+
+/// Sale history keyed by (nft_address, sale_id)
+pub fn sale_history<'a>() -> IndexedMap<'a, (&'a str, &'a str), SaleInfo, SaleIndexes<'a>> {
+    let indexes = SaleIndexes {
+        collection: MultiIndex::new(|sale: &SaleInfo| (sale.nft_address.clone(), sale.time), "sale", "sale__collection"),
+        buyer: MultiIndex::new(|sale: &SaleInfo| (sale.to.clone(), sale.time), "sale", "sale__buyer"),
+        seller: MultiIndex::new(|sale: &SaleInfo| (sale.from.clone(), sale.time), "sale", "sale__seller"),
+        token: MultiIndex::new(
+            |sale: &SaleInfo| (sale.nft_address.clone(), sale.token_id.clone(), sale.time),
+            "sale",
+            "sale__token",
+        ),
+        time: MultiIndex::new(|sale: &SaleInfo| sale.time, "sale", "sale__time"),
+    };
+    IndexedMap::new("sale", indexes)
+}
+
+/// Escrowed buyer offers keyed by (nft_address, token_id, bidder)
+pub struct AuctionIndexes<'a> {
+    pub token: MultiIndex<'a, (String, String), Auction, (String, String)>,
+}
+
+impl<'a> IndexList<Auction> for AuctionIndexes<'a> {
+    fn get_indexes(&'_ self) -> Box<dyn Iterator<Item = &'_ dyn Index<Auction>> + '_> {
+        let v: Vec<&dyn Index<Auction>> = vec![&self.token];
+        Box::new(v.into_iter())
+    }
+}
+
+/// English auctions keyed by (nft_address, auction_id)
+pub fn auctions<'a>() -> IndexedMap<'a, (&'a str, &'a str), Auction, AuctionIndexes<'a>> {
+    let indexes = AuctionIndexes {
+        token: MultiIndex::new(
+            |auction: &Auction| (auction.nft_address.clone(), auction.token_id.clone()),
+            "auctions",
+            "auctions__token",
+        ),
+    };
+    IndexedMap::new("auctions", indexes)
+}
+
+pub fn token_offers<'a>() -> IndexedMap<'a, (&'a str, &'a str, &'a str), TokenOffer, TokenOfferIndexes<'a>> {
+    let indexes = TokenOfferIndexes {
+        bidder: MultiIndex::new(|offer: &TokenOffer| offer.bidder.clone(), "token_offers", "token_offers__bidder"),
+    };
+    IndexedMap::new("token_offers", indexes)
+}
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct State {
     pub owner:String,
     pub new : bool,
-   
+    /// Marketplace fee taken from every sale
+    #[serde(default)]
+    pub fee: Decimal,
+    #[serde(default)]
+    pub fee_collector: String,
+    /// Volume discounts, the highest tier a collection has reached applies
+    #[serde(default)]
+    pub fee_tiers: Vec<FeeTier>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PendingOwner {
+    pub owner: String,
+    /// Block time in seconds after which the proposal can no longer be accepted
+    pub expires: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct FeeTier {
+    /// Native denom or cw20 contract address the volume is counted in
+    pub denom: String,
+    /// Collection volume (TVL) needed to reach the tier
+    pub min_volume: Uint128,
+    /// Portion of the fee waived
+    pub discount: Decimal,
 }
 
 #[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
 pub struct Offering {
+    /// Set on offerings stored before the indexes by the migration
+    #[serde(default)]
+    pub nft_address: String,
     pub token_id: String,
     pub seller: String,
     pub list_price: Asset,
+    pub dutch_auction: Option<DutchAuction>,
+    pub expires_at: Option<u64>,
+}
+
+/// Price schedule of a Dutch auction offering, `Offering.list_price` is the start price
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+pub struct DutchAuction {
+    pub floor_price: Uint128,
+    pub start_time: u64,
+    pub end_time: u64,
+    pub step: Option<u64>,
 }
 
+impl Offering {
+    pub fn is_expired(&self, time: u64) -> bool {
+        matches!(self.expires_at, Some(expires_at) if expires_at <= time)
+    }
 
-#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
-pub struct Asset {
-    pub denom:String,
-    pub amount:Uint128
+    /// Dutch auctions are kept apart in the price index, their price changes over time
+    pub fn pricing(&self) -> &'static str {
+        match self.dutch_auction {
+            Some(_) => DUTCH_AUCTION,
+            None => FIXED_PRICE,
+        }
+    }
+
+    /// Lowest price the offering can reach, the floor price for Dutch auctions
+    pub fn min_price(&self) -> Uint128 {
+        match &self.dutch_auction {
+            Some(auction) => auction.floor_price,
+            None => self.list_price.amount,
+        }
+    }
+
+    /// Price of the offering at block time `time`
+    pub fn current_price(&self, time: u64) -> Uint128 {
+        let auction = match &self.dutch_auction {
+            Some(auction) => auction,
+            None => return self.list_price.amount,
+        };
+        if time <= auction.start_time {
+            return self.list_price.amount;
+        }
+        if time >= auction.end_time {
+            return auction.floor_price;
+        }
+
+        let mut elapsed = time - auction.start_time;
+        if let Some(step) = auction.step.filter(|step| *step > 0) {
+            elapsed -= elapsed % step;
+        }
+        let decay = (self.list_price.amount - auction.floor_price)
+            .multiply_ratio(elapsed, auction.end_time - auction.start_time);
+        self.list_price.amount - decay
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+pub struct Auction {
+    /// Set on auctions stored before the indexes by the migration
+    #[serde(default)]
+    pub nft_address: String,
+    pub token_id: String,
+    pub seller: String,
+    pub reserve_price: Asset,
+    pub min_increment: Uint128,
+    pub end_time: u64,
+    pub highest_bid: Option<Bid>,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+pub struct Bid {
+    pub bidder: String,
+    pub amount: Uint128,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+pub struct TokenOffer {
+    pub nft_address: String,
+    pub token_id: String,
+    pub bidder: String,
+    pub price: Asset,
+    pub expires: u64,
+}
+
+/// `price` is paid per token, `quantity` tokens are still wanted
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+pub struct CollectionOffer {
+    pub nft_address: String,
+    pub bidder: String,
+    pub price: Asset,
+    pub quantity: u64,
+    pub expires: u64,
+}
+
+/// Owner proposal to move escrowed nfts to `recipient`, executable from `executable_at`
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+pub struct NftTransfer {
+    pub id: u64,
+    pub nft_address: String,
+    pub token_ids: Vec<String>,
+    pub recipient: String,
+    pub executable_at: u64,
+    /// Listed tokens whose seller agreed to the transfer
+    pub consents: Vec<TransferConsent>,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+pub struct TransferConsent {
+    pub token_id: String,
+    pub seller: String,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
@@ -46,6 +383,7 @@ pub struct UserInfo {
 pub struct SaleInfo {
     pub from :String,
     pub to: String,
+    /// Native denom or cw20 contract address
     pub denom:String,
     pub amount:Uint128,
     pub time : u64,
@@ -60,14 +398,86 @@ pub struct PriceInfo {
    pub total_hope: Uint128
 }
 
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct VolumeInfo {
+    pub info: AssetInfo,
+    pub amount: Uint128,
+    pub sales: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema, Default)]
+pub struct GlobalStats {
+    pub total_sales: u64,
+    pub active_listings: u64,
+    pub unique_buyers: u64,
+    pub unique_sellers: u64,
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 
 pub struct CollectionInfo{
     pub nft_address :String,
+    /// Last issued offering id, ids are never reused
     pub offering_id:u64,
     pub royalty_portion:Decimal,
-    pub sale_id : u64
+    pub sale_id : u64,
+    #[serde(default)]
+    pub auction_id : u64,
+    /// Number of live offerings
+    #[serde(default)]
+    pub num_offerings : u64,
+    /// Overrides the marketplace fee for this collection
+    #[serde(default)]
+    pub fee : Option<Decimal>,
+    #[serde(default)]
+    pub royalty_mode : RoyaltyMode
+}
+
+/// Where the royalty paid on a sale comes from
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum RoyaltyMode {
+    /// `royalty_portion` split between the collection `MEMBERS`
+    #[default]
+    Static,
+    /// CW-2981 `RoyaltyInfo` of the nft contract
+    Cw2981,
+    /// CW-2981 `RoyaltyInfo`, capped at `royalty_portion` of the price
+    Cw2981Capped,
+}
+
+/// Admin permissions the owner can delegate, the owner itself holds every role
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    /// Adds and updates collections and accepted cw20 tokens
+    Curator,
+    /// Sets the marketplace fee and fee collector
+    Treasury,
+    /// Pauses trading
+    Operator,
+    /// Imports offerings, tvl and sale history
+    Migrator,
+}
+
+impl Role {
+    pub const ALL: [Role; 4] = [Role::Curator, Role::Treasury, Role::Operator, Role::Migrator];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::Curator => "curator",
+            Role::Treasury => "treasury",
+            Role::Operator => "operator",
+            Role::Migrator => "migrator",
+        }
+    }
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]